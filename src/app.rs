@@ -13,172 +13,305 @@
 //limitations under the License.
 
 
-use crate::component::camera_component::CameraComponent;
-use crate::component::transform_component::TransformComponent;
-use crate::scene::SceneCreate;
-use crate::scene::{scene_one::SceneOne, Scene};
+pub mod builder;
 
-use crate::system::controller_system::ControllerSystem;
-use crate::system::renderer_system::RendererSystem;
-use anyhow::Result;
+use crate::display::{DisplaySettings, FrameLimiter};
+use crate::profiler::Profiler;
+use crate::replay::{InputRecorder, InputReplay};
+use crate::resource::input_state::{Binding, InputEvent, InputState};
+use crate::scene::lock::RecoverLock;
+use crate::scene::Scene;
+use builder::Startup;
+
+use crate::system::simulation_system::SimulationSystem;
+use crate::stats::{FrameSample, FrameStats};
+use crate::system::renderer_system::{RenderStats, RendererSystem};
+use anyhow::{Context, Result};
 //use nalgebra_glm::{translate, Mat4, Vec3};
 use core::f32;
-use glam::{Mat4, Vec3};
+use glam::Vec2;
 use std::collections::HashMap;
-use std::f32::consts::PI;
 use std::sync::Arc;
 //use no_deadlocks::prelude::{RwLock};
 use std::sync::RwLock;
 
-use std::time::Instant;
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, warn};
 use winit::application::ApplicationHandler;
 use winit::event::{DeviceEvent, DeviceId, MouseScrollDelta, StartCause, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoop};
-use winit::window::{Window, WindowAttributes, WindowId};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::{CursorGrabMode, Window, WindowId};
+
+//Frames captured when the capture_trace action is pressed
+const TRACE_HOTKEY_FRAMES: u64 = 120;
 
 #[derive(Clone, Copy)]
 pub struct UserEvent;
 
-#[derive(Default, Clone, Copy)]
-pub struct Input {
-    w: bool,
-    a: bool,
-    s: bool,
-    d: bool,
-    q: bool,
-    e: bool,
-}
-
 pub struct App {
     windows: HashMap<WindowId, Arc<Window>>,
     //entities: Vec<Entity>,
     //Systems
     renderer_system: RendererSystem,
-    controller_system: ControllerSystem,
+    simulation_system: SimulationSystem,
     scenes: Vec<Arc<RwLock<Scene>>>,
     last_new_events_time: Option<Instant>,
     last_window_events_time: Option<Instant>,
-    current_input: Input,
+    //None when the input plugin isn't added
+    input_state: Option<Arc<RwLock<InputState>>>,
+    cursor_grab_applied: Option<bool>,
+    //Game loops run so far, input events are tagged with the frame they feed into
+    frame: u64,
+    //Set while recording or replaying, frames then advance by this instead of wall clock time
+    fixed_delta: Option<Duration>,
+    recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
+    display_settings: DisplaySettings,
+    frame_limiter: FrameLimiter,
+    focused: bool,
+    occluded: bool,
+    frame_stats: Option<FrameStats>,
+    profiler: Option<Profiler>,
 }
 
 impl App {
-    pub fn new(event_loop: &EventLoop<UserEvent>) -> Result<Self> {
-        let window = Arc::new(
-            event_loop
-                .create_window(WindowAttributes::default()
-                .with_title("Balloon Engine"))
-                .unwrap(),
-        );
+    //Use AppBuilder, which resolves the scene, systems and plugins first
+    fn new(event_loop: &EventLoop<UserEvent>, startup: Startup) -> Result<Self> {
+        let Startup {
+            config,
+            scene,
+            simulation_system,
+            session,
+            input_state,
+            frame_stats,
+            profiler,
+            pipelines,
+        } = startup;
+        let mut display_settings = config.display;
+        let fixed_delta = session.fixed_delta();
+        if let Some(fixed_delta) = fixed_delta {
+            //Keep simulated time in step with the wall clock while recording or watching a replay
+            display_settings.target_fps = Some(1.0 / fixed_delta.as_secs_f32());
+        }
+
+        let window = Arc::new(event_loop.create_window(config.window.attributes())?);
         let mut windows: HashMap<WindowId, Arc<Window>> = HashMap::new();
         windows.insert(window.id(), Arc::clone(&window));
-        //let scene_type = SceneOne;
-        //let scene = Scene::<SceneOne>::new();
-        //let vals: Vec<Box<MyStruct<dyn Debug>>> = vec![
-        //Box::new(MyStruct { foo: 5, bar: 6 }),
 
-        let scene_one = <Scene as SceneCreate<SceneOne>>::new();
+        if let Some(input_state) = input_state.as_ref() {
+            //Start in mouse look, the toggle_cursor_grab action releases it
+            input_state.write_or_recover().set_cursor_grabbed(true);
+        }
 
-        let renderer_system =
-            RendererSystem::new(Arc::clone(&window), event_loop, Arc::clone(&scene_one))
-                .expect("Err making renderer system");
-        let controller_system = ControllerSystem::new();
+        let mut renderer_system =
+            RendererSystem::new(Arc::clone(&window), event_loop, Arc::clone(&scene), &config)
+                .context("Failed to create the renderer")?;
+        for (key, create) in pipelines {
+            let pipeline = create(renderer_system.device())
+                .with_context(|| format!("Failed to create pipeline {key}"))?;
+            renderer_system.register_pipeline(&key, pipeline);
+        }
 
         let mut scenes: Vec<Arc<RwLock<Scene>>> = vec![];
-        scenes.push(scene_one);
+        scenes.push(scene);
 
-        Ok(Self {
+        let mut app = Self {
             windows,
             renderer_system,
-            controller_system,
+            simulation_system,
             scenes,
             last_new_events_time: None,
             last_window_events_time: None,
-            current_input: Input::default(),
-        })
+            input_state,
+            cursor_grab_applied: None,
+            frame: 0,
+            fixed_delta,
+            recorder: session.recorder,
+            replay: session.replay,
+            display_settings,
+            frame_limiter: FrameLimiter::new(display_settings.target_fps),
+            focused: true,
+            occluded: false,
+            frame_stats,
+            profiler,
+        };
+        let size = window.inner_size();
+        app.handle_input(InputEvent::WindowResized(Vec2::new(
+            size.width as f32,
+            size.height as f32,
+        )));
+        Ok(app)
     }
 
-    fn game_loop(&self) {
-        self.controller_system.run(self.scenes[0].clone(), self.current_input);
+    //All input goes through here so it can be recorded, live input is dropped while a replay runs
+    fn handle_input(&mut self, event: InputEvent) {
+        let Some(input_state) = self.input_state.as_ref() else {
+            return;
+        };
+        if self.replay.is_some() {
+            return;
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(err) = recorder.record(self.frame, &event) {
+                warn!("{err:#}, stopping recording");
+                self.recorder = None;
+            }
+        }
+        input_state.write_or_recover().apply(&event);
+    }
+
+    fn apply_replayed_input(&mut self) {
+        let Some(replay) = self.replay.as_mut() else {
+            return;
+        };
+        let events = replay.events_for(self.frame);
+        let finished = replay.is_finished();
+        if let Some(input_state) = self.input_state.as_ref() {
+            let mut input_state = input_state.write_or_recover();
+            for event in events.iter() {
+                input_state.apply(event);
+            }
+        }
+        if finished {
+            info!("Replay finished after {} frames, back to live input", self.frame);
+            self.replay = None;
+            self.fixed_delta = None;
+        }
+    }
 
-        let scene = self.scenes[0].read().unwrap();
-        if let Some(cameras) = scene.get_component_vec::<CameraComponent>() {
-            let mut cameras = cameras.write().unwrap();
-            let transforms = scene.get_component_vec::<TransformComponent>().unwrap();
-            let mut transforms = transforms.write().unwrap();
+    fn update_frame_rate(&mut self) {
+        let settings = &self.display_settings;
+        let background = settings.throttle_in_background && (!self.focused || self.occluded);
+        let target_fps = if background {
+            Some(settings.background_fps)
+        } else {
+            settings.target_fps
+        };
+        self.frame_limiter.set_target_fps(target_fps);
+    }
 
-            let zip = transforms.iter_mut().zip(cameras.iter_mut());
-            let iter =
-                zip.filter_map(|(transform, camera)| Some((transform.as_mut()?, camera.as_mut()?)));
+    fn flush_recording(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(err) = recorder.flush() {
+                warn!("{err:#}, stopping recording");
+                self.recorder = None;
+            }
+        }
+    }
 
-            for (transform_component, camera_component) in iter {
-                if camera_component.is_active {
-                    if self.current_input.a {
-                        transform_component.transform_op(|transform| {
-                            Mat4::from_translation(Vec3::new(0.2, 0.0, 0.0)) * transform
-                        });
-                    }
-                    if self.current_input.d {
-                        transform_component.transform_op(|transform| {
-                            Mat4::from_translation(Vec3::new(-0.2, 0.0, 0.0)) * transform
-                        });
-                    }
-                    if self.current_input.s {
-                        transform_component.transform_op(|transform| {
-                            Mat4::from_translation(Vec3::new(0.0, 0.0, 0.2)) * transform
-                        });
-                    }
-                    if self.current_input.w {
-                        transform_component.transform_op(|transform| {
-                            Mat4::from_translation(Vec3::new(0.0, 0.0, -0.2)) * transform
-                        });
-                    }
-                    if self.current_input.q {
-                        transform_component.transform_op(|transform| {
-                            Mat4::from_axis_angle(Vec3::Z, PI / 120.0) * transform
-                        });
-                    }
-                    if self.current_input.e {
-                        transform_component.transform_op(|transform| {
-                            Mat4::from_axis_angle(Vec3::NEG_Z, PI / 120.0) * transform
-                        });
-                    }
-                }
+    //Returns how long the simulation step took
+    fn game_loop(&mut self, delta: Duration) -> Duration {
+        self.apply_replayed_input();
+        let delta = self.fixed_delta.unwrap_or(delta);
+        let step_started = Instant::now();
+        self.simulation_system.step(&self.scenes[0], delta);
+        let step_time = step_started.elapsed();
+        let grabbed = self
+            .input_state
+            .as_ref()
+            .is_some_and(|input_state| input_state.read_or_recover().cursor_grabbed());
+        if self.cursor_grab_applied != Some(grabbed) {
+            for window in self.windows.values() {
+                apply_cursor_grab(window, grabbed);
             }
+            self.cursor_grab_applied = Some(grabbed);
         }
+        self.flush_recording();
+        self.frame += 1;
+
         //info!("Game loop deb3");
+        step_time
+    }
+}
+
+fn apply_cursor_grab(window: &Window, grabbed: bool) {
+    let result = if grabbed {
+        //Locked isn't available everywhere (e.g. Windows), confining is the next best thing
+        window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+    if let Err(err) = result {
+        warn!("Could not change cursor grab: {err}");
     }
+    window.set_cursor_visible(!grabbed);
 }
+
 impl ApplicationHandler<UserEvent> for App {
     fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
-        match self.last_new_events_time {
+        if !self.frame_limiter.wait_for_frame() {
+            return;
+        }
+        let delta = match self.last_new_events_time {
             Some(time) => {
                 let now = Instant::now();
                 let elapsed = now.saturating_duration_since(time);
-                println!(
-                    "Elapsed:{:?}, fps:{:?}, entities: {}",
-                    elapsed,
-                    1f64 / elapsed.as_secs_f64(),
-                    self.scenes[0].read().unwrap().entities_index
-                );
                 self.last_new_events_time = Some(now);
+                elapsed
             }
             None => {
                 let now = Instant::now();
                 self.last_new_events_time = Some(now);
+                Duration::ZERO
             }
-        }
-
-
-        self.game_loop();
-        self.renderer_system.redraw();
+        };
 
+        let frame = self.frame;
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.begin_frame(frame);
+        }
+        let frame_span = info_span!("frame", frame).entered();
+        let controller_time = self.game_loop(delta);
+        //Nothing on screen to update while hidden, keep simulating
+        let render_stats = if !(self.occluded && self.display_settings.throttle_in_background) {
+            self.renderer_system.redraw()
+        } else {
+            RenderStats::default()
+        };
+        if let Some(frame_stats) = self.frame_stats.as_mut() {
+            frame_stats.record(FrameSample {
+                frame,
+                frame_time: delta,
+                controller_time,
+                render_record_time: render_stats.record_time,
+                draw_calls: render_stats.draw_calls,
+                triangles: render_stats.triangles,
+                state_changes: render_stats.state_changes,
+                entities: self.scenes[0].read_or_recover().entities_index,
+            });
+        }
+        drop(frame_span);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame(frame);
+            let capture_pressed = self
+                .input_state
+                .as_ref()
+                .is_some_and(|input_state| input_state.read_or_recover().just_pressed("capture_trace"));
+            if capture_pressed {
+                let first = frame + 1;
+                profiler.capture(format!("trace_frame_{first}.json"), first..first + TRACE_HOTKEY_FRAMES);
+            }
+        }
     }
     fn user_event(&mut self, event_loop: &ActiveEventLoop, user_event: UserEvent) {
         // Handle user event.
     }
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {}
 
+    fn exiting(&mut self, event_loop: &ActiveEventLoop) {
+        self.flush_recording();
+        if let Some(frame_stats) = self.frame_stats.as_mut() {
+            frame_stats.log_summary();
+            frame_stats.flush();
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.finish();
+        }
+    }
+
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
@@ -188,9 +321,16 @@ impl ApplicationHandler<UserEvent> for App {
         match event {
             WindowEvent::CloseRequested {} => {
                 let _ = self.windows.remove(&window_id);
+                if self.windows.is_empty() {
+                    event_loop.exit();
+                }
             }
-            WindowEvent::Resized(_) => {
+            WindowEvent::Resized(size) => {
                 info!("Resized window");
+                self.handle_input(InputEvent::WindowResized(Vec2::new(
+                    size.width as f32,
+                    size.height as f32,
+                )));
                 self.renderer_system.recreate_swapchain = true;
                 return;
             }
@@ -200,80 +340,76 @@ impl ApplicationHandler<UserEvent> for App {
             WindowEvent::DroppedFile(path_buf) => (),
             WindowEvent::HoveredFile(path_buf) => (),
             WindowEvent::HoveredFileCancelled => (),
-            WindowEvent::Focused(_) => (),
+            WindowEvent::Focused(focused) => {
+                self.focused = focused;
+                self.update_frame_rate();
+                if !focused {
+                    //Key ups go to whichever window has focus now, don't leave keys stuck down
+                    self.handle_input(InputEvent::FocusLost);
+                }
+            }
             WindowEvent::KeyboardInput {
                 device_id,
                 event,
                 is_synthetic,
             } => match event.physical_key {
-                winit::keyboard::PhysicalKey::Code(key_code) => match key_code {
-                    winit::keyboard::KeyCode::KeyT => {}
-                    winit::keyboard::KeyCode::KeyA => {
-                        self.current_input.a = event.state.is_pressed();
-                    }
-                    winit::keyboard::KeyCode::KeyD => {
-                        self.current_input.d = event.state.is_pressed();
-                    }
-                    winit::keyboard::KeyCode::KeyS => {
-                        self.current_input.s = event.state.is_pressed();
-                    }
-                    winit::keyboard::KeyCode::KeyW => {
-                        self.current_input.w = event.state.is_pressed();
-                    }
-                    winit::keyboard::KeyCode::KeyQ => {
-                        self.current_input.q = event.state.is_pressed();
-                    }
-                    winit::keyboard::KeyCode::KeyE => {
-                        self.current_input.e = event.state.is_pressed();
-                    }
-                    winit::keyboard::KeyCode::Escape => {
-                        std::process::exit(0);
+                PhysicalKey::Code(KeyCode::Escape) => {
+                    event_loop.exit();
+                }
+                PhysicalKey::Code(key_code) => {
+                    let binding = Binding::Key(key_code);
+                    if event.state.is_pressed() {
+                        self.handle_input(InputEvent::Pressed(binding));
+                    } else {
+                        self.handle_input(InputEvent::Released(binding));
                     }
-                    default => {}
-                },
-                winit::keyboard::PhysicalKey::Unidentified(native_key_code) => todo!(),
+                }
+                PhysicalKey::Unidentified(native_key_code) => {
+                    debug!("Ignoring unidentified key {:?}", native_key_code);
+                }
             },
             WindowEvent::ModifiersChanged(modifiers) => (),
             WindowEvent::Ime(ime) => (),
             WindowEvent::CursorMoved {
                 device_id,
                 position,
-            } => (),
+            } => {
+                self.handle_input(InputEvent::CursorMoved(Some(Vec2::new(
+                    position.x as f32,
+                    position.y as f32,
+                ))));
+            }
             WindowEvent::CursorEntered { device_id } => (),
-            WindowEvent::CursorLeft { device_id } => (),
+            WindowEvent::CursorLeft { device_id } => {
+                self.handle_input(InputEvent::CursorMoved(None));
+            }
             WindowEvent::MouseWheel {
                 device_id,
                 delta,
                 phase,
             } => match delta {
                 MouseScrollDelta::LineDelta(x, y) => {
-                    /*let scene = self.scenes[0].write().unwrap();
-                    if let Some(mut camera_components) =
-                        scene.borrow_component_vec_mut::<CameraComponent>()
-                    {
-                        for camera in camera_components.iter_mut() {
-                            if let Some(camera) = camera.as_mut() {
-                                if camera.is_active {
-                                    camera.fovy = (camera.fovy - (0.1 * y)).clamp(0.1, f32::MAX);
-
-                                    self.renderer_system.recreate_swapchain = true;
-                                    println!("fov{}", camera.fovy);
-                                    //break;
-                                }
-                            }
-                        }
-                    }
-                    drop(scene);*/
+                    self.handle_input(InputEvent::Scroll(Vec2::new(x, y)));
                 }
-                MouseScrollDelta::PixelDelta(x) => {
-                    info!("Pixel delta, {:?}", x);
+                MouseScrollDelta::PixelDelta(position) => {
+                    self.handle_input(InputEvent::ScrollPixels(Vec2::new(
+                        position.x as f32,
+                        position.y as f32,
+                    )));
                 }
             },
             WindowEvent::MouseInput {
                 device_id,
                 state,
                 button,
-            } => (),
+            } => {
+                let binding = Binding::MouseButton(button);
+                if state.is_pressed() {
+                    self.handle_input(InputEvent::Pressed(binding));
+                } else {
+                    self.handle_input(InputEvent::Released(binding));
+                }
+            }
             WindowEvent::PinchGesture {
                 device_id,
                 delta,
@@ -306,7 +442,10 @@ impl ApplicationHandler<UserEvent> for App {
                 inner_size_writer,
             } => (),
             WindowEvent::ThemeChanged(theme) => (),
-            WindowEvent::Occluded(_) => (),
+            WindowEvent::Occluded(occluded) => {
+                self.occluded = occluded;
+                self.update_frame_rate();
+            }
             WindowEvent::RedrawRequested => {
                 //self.renderer_system.redraw();
             }
@@ -321,28 +460,10 @@ impl ApplicationHandler<UserEvent> for App {
     ) {
         match event {
             DeviceEvent::MouseMotion { delta } => {
-                let scene = self.scenes[0].read().unwrap();
-                let cameras = scene.get_component_vec::<CameraComponent>().unwrap();
-                let mut cameras = cameras.write().unwrap();
-                let transforms = scene.get_component_vec::<TransformComponent>().unwrap();
-                let mut transforms = transforms.write().unwrap();
-
-                let zip = transforms.iter_mut().zip(cameras.iter_mut());
-                let iter = zip.filter_map(|(transform, camera)| {
-                    Some((transform.as_mut()?, camera.as_mut()?))
-                });
-
-                for (transform_component, camera) in iter {
-                    if camera.is_active {
-                        let rot0 = Mat4::from_axis_angle(Vec3::Y, -delta.0 as f32 / 100.0);
-                        let rot1 = Mat4::from_axis_angle(Vec3::X, -delta.1 as f32 / 100.0);
-                        transform_component.transform_op(|transform| {
-                            Mat4::from_axis_angle(Vec3::Y, -delta.0 as f32 / 100.0)
-                                * Mat4::from_axis_angle(Vec3::X, -delta.1 as f32 / 100.0)
-                                * transform
-                        });
-                    }
-                }
+                self.handle_input(InputEvent::MouseMotion(Vec2::new(
+                    delta.0 as f32,
+                    delta.1 as f32,
+                )));
             }
             DeviceEvent::Added => {}
             DeviceEvent::Removed => {}
@@ -360,6 +481,10 @@ impl ApplicationHandler<UserEvent> for App {
         // Handle device event.
     }
 
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        event_loop.set_control_flow(self.frame_limiter.control_flow());
+    }
+
     //fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
     //    //info!("About to wait event");
     //    //event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(Instant::now()+Duration::new(1, 0)));
@@ -0,0 +1,273 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::any::TypeId;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use tracing::{info, warn};
+use vulkano::device::Device;
+use winit::event_loop::EventLoop;
+
+use crate::{
+    config::EngineConfig,
+    headless::HeadlessRunner,
+    plugin::Plugin,
+    profiler::Profiler,
+    replay::{ReplayMode, ReplaySession},
+    resource::input_state::{InputMap, InputState},
+    scene::{lock::RecoverLock, Scene},
+    stats::FrameStats,
+    system::{
+        renderer_system::pipelines::RenderPipeline, simulation_system::SimulationSystem, System,
+    },
+};
+
+use super::{App, UserEvent};
+
+type SceneFactory = Box<dyn FnOnce(u64) -> Result<Arc<RwLock<Scene>>>>;
+type ResourceInsert = Box<dyn FnOnce(&mut Scene)>;
+pub(super) type PipelineFactory = Box<dyn FnOnce(Arc<Device>) -> Result<Box<dyn RenderPipeline>>>;
+
+//Everything App::new needs once the builder has resolved it
+pub(super) struct Startup {
+    pub(super) config: EngineConfig,
+    pub(super) scene: Arc<RwLock<Scene>>,
+    pub(super) simulation_system: SimulationSystem,
+    pub(super) session: ReplaySession,
+    pub(super) input_state: Option<Arc<RwLock<InputState>>>,
+    pub(super) frame_stats: Option<FrameStats>,
+    pub(super) profiler: Option<Profiler>,
+    pub(super) pipelines: Vec<(String, PipelineFactory)>,
+}
+
+//Assembles a game from plugins, systems, scenes and resources then runs it. Without the
+//render plugin the scene is ticked headless for config.headless_frames frames.
+//
+//let mut app = AppBuilder::new(config);
+//app.add_plugin(DefaultPlugins::new(profiler))
+//    .add_scene("scene_one", |seed| Ok(<Scene as SceneCreate<SceneOne>>::new(seed)));
+//app.run()?;
+pub struct AppBuilder {
+    config: EngineConfig,
+    replay_mode: ReplayMode,
+    plugins: HashSet<TypeId>,
+    scenes: Vec<(String, SceneFactory)>,
+    systems: Vec<Box<dyn System>>,
+    resources: Vec<ResourceInsert>,
+    pipelines: Vec<(String, PipelineFactory)>,
+    rendering: bool,
+    input_map: Option<InputMap>,
+    frame_stats: Option<FrameStats>,
+    profiler: Option<Profiler>,
+    //The first plugin that failed to build, returned from run so bad config still stops startup
+    plugin_error: Option<anyhow::Error>,
+}
+
+impl AppBuilder {
+    pub fn new(config: EngineConfig) -> Self {
+        AppBuilder {
+            config,
+            replay_mode: ReplayMode::Off,
+            plugins: HashSet::new(),
+            scenes: vec![],
+            systems: vec![],
+            resources: vec![],
+            pipelines: vec![],
+            rendering: false,
+            input_map: None,
+            frame_stats: None,
+            profiler: None,
+            plugin_error: None,
+        }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut EngineConfig {
+        &mut self.config
+    }
+
+    pub fn set_replay_mode(&mut self, replay_mode: ReplayMode) -> &mut Self {
+        self.replay_mode = replay_mode;
+        self
+    }
+
+    //Each plugin type is only built once, adding it again is ignored. A plugin that fails
+    //to build makes run return its error.
+    pub fn add_plugin<P: Plugin + 'static>(&mut self, plugin: P) -> &mut Self {
+        if !self.plugins.insert(TypeId::of::<P>()) {
+            warn!("Plugin {} already added", plugin.name());
+            return self;
+        }
+        let name = plugin.name().to_string();
+        if let Err(err) = plugin.build(self) {
+            if self.plugin_error.is_none() {
+                self.plugin_error = Some(err.context(format!("Plugin {name} failed to build")));
+            } else {
+                warn!("Plugin {name} failed to build: {err:#}");
+            }
+        }
+        self
+    }
+
+    pub fn has_plugin<P: Plugin + 'static>(&self) -> bool {
+        self.plugins.contains(&TypeId::of::<P>())
+    }
+
+    //Run every frame after the controllers, in the order added
+    pub fn add_system(&mut self, system: impl System + 'static) -> &mut Self {
+        self.systems.push(Box::new(system));
+        self
+    }
+
+    //Scenes are built from the run's seed, config.scene picks which one starts
+    pub fn add_scene(
+        &mut self,
+        name: &str,
+        create: impl FnOnce(u64) -> Result<Arc<RwLock<Scene>>> + 'static,
+    ) -> &mut Self {
+        self.scenes.push((name.to_string(), Box::new(create)));
+        self
+    }
+
+    //Inserted into the starting scene once it has been built
+    pub fn insert_resource<R: 'static + Send + Sync>(&mut self, resource: R) -> &mut Self {
+        self.resources.push(Box::new(move |scene: &mut Scene| {
+            scene.insert_resource(resource);
+        }));
+        self
+    }
+
+    //Created once the renderer has a device, ignored when running headless.
+    //app.register_pipeline("outline", |device| Ok(Box::new(Outline::new(device)?)));
+    pub fn register_pipeline(
+        &mut self,
+        key: &str,
+        create: impl FnOnce(Arc<Device>) -> Result<Box<dyn RenderPipeline>> + 'static,
+    ) -> &mut Self {
+        self.pipelines.push((key.to_string(), Box::new(create)));
+        self
+    }
+
+    pub fn enable_rendering(&mut self) -> &mut Self {
+        self.rendering = true;
+        self
+    }
+
+    pub fn enable_input(&mut self, map: InputMap) -> &mut Self {
+        self.input_map = Some(map);
+        self
+    }
+
+    pub fn set_frame_stats(&mut self, frame_stats: FrameStats) -> &mut Self {
+        self.frame_stats = Some(frame_stats);
+        self
+    }
+
+    pub fn set_profiler(&mut self, profiler: Profiler) -> &mut Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        if let Some(err) = self.plugin_error.take() {
+            return Err(err);
+        }
+        let session = ReplaySession::start(std::mem::replace(&mut self.replay_mode, ReplayMode::Off))?;
+        let scene = self.build_scene(session.seed)?;
+        let input_state = self.input_map.take().map(|map| {
+            scene
+                .write_or_recover()
+                .insert_resource(InputState::new(map))
+        });
+        let mut simulation_system = SimulationSystem::new();
+        for system in self.systems.drain(..) {
+            simulation_system.add_system(system);
+        }
+
+        if !self.rendering {
+            return self.run_headless(scene, simulation_system, session);
+        }
+
+        let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
+        let mut app = App::new(
+            &event_loop,
+            Startup {
+                config: self.config.clone(),
+                scene,
+                simulation_system,
+                session,
+                input_state,
+                frame_stats: self.frame_stats.take(),
+                profiler: self.profiler.take(),
+                pipelines: std::mem::take(&mut self.pipelines),
+            },
+        )?;
+        event_loop.run_app(&mut app)?;
+        Ok(())
+    }
+
+    fn build_scene(&mut self, seed: u64) -> Result<Arc<RwLock<Scene>>> {
+        let position = self
+            .scenes
+            .iter()
+            .position(|(name, _)| *name == self.config.scene)
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.scenes.iter().map(|(name, _)| name.as_str()).collect();
+                anyhow!("Unknown scene {}, expected one of {names:?}", self.config.scene)
+            })?;
+        let (_, create) = self.scenes.swap_remove(position);
+        let scene = create(seed)?;
+        {
+            let mut scene = scene.write_or_recover();
+            scene.insert_resource(self.config.clone());
+            for insert in self.resources.drain(..) {
+                insert(&mut scene);
+            }
+        }
+        Ok(scene)
+    }
+
+    fn run_headless(
+        &mut self,
+        scene: Arc<RwLock<Scene>>,
+        simulation_system: SimulationSystem,
+        session: ReplaySession,
+    ) -> Result<()> {
+        if session.recorder.is_some() {
+            warn!("Headless runs have no live input, nothing will be recorded");
+        }
+        let seed = session.seed;
+        let mut runner = HeadlessRunner::new(scene).with_simulation_system(simulation_system);
+        if let Some(replay) = session.replay {
+            runner = runner.with_replay(replay);
+        }
+        let started = Instant::now();
+        runner.run_frames(self.config.headless_frames);
+        info!(
+            "Simulated {} frames in {:?}, seed {}, disabled controllers: {:?}",
+            runner.frame(),
+            started.elapsed(),
+            seed,
+            runner.disabled_controllers()
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 pub mod camera_component;
 pub mod component_vec;
 pub mod controller;
+pub mod instanced_mesh_component;
+pub mod light_component;
 pub mod mesh_filter_component;
 pub mod mesh_renderer_component;
 pub mod transform_component;
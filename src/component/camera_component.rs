@@ -11,6 +11,8 @@ pub struct CameraComponent {
     pub far: f32,
     pub perspective: Option<Mat4>,
     pub is_active: bool,
+    //Last aspect ratio the perspective was built with, lets fovy change without the renderer
+    pub aspect: f32,
 }
 impl CameraComponent {
     pub fn new() -> Self {
@@ -23,11 +25,19 @@ impl CameraComponent {
             far,
             perspective: None,
             is_active: true,
+            aspect: 1.0,
         }
     }
     pub fn update_perspective(&mut self, aspect: f32) {
         info!("Setting perspective, aspect: {}", aspect);
+        self.aspect = aspect;
         self.perspective = Some(Mat4::perspective_lh(self.fovy, aspect, self.near, self.far));
     }
+    pub fn set_fovy(&mut self, fovy: f32) {
+        self.fovy = fovy;
+        if self.perspective.is_some() {
+            self.perspective = Some(Mat4::perspective_lh(self.fovy, self.aspect, self.near, self.far));
+        }
+    }
 }
 impl Component for CameraComponent {}
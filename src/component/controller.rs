@@ -13,21 +13,46 @@
 //limitations under the License.
 
 
+use crate::resource::mailbox::Message;
 use crate::scene::{Scene};
 
+use anyhow::Result;
 use std::fmt::Debug;
 use std::sync::{Arc, RwLock};
 
 use super::Component;
 
+pub mod animation_controller;
+pub mod behaviour_tree_controller;
 pub mod color_controller;
+pub mod fly_camera_controller;
+pub mod follow_camera_controller;
+pub mod orbit_camera_controller;
 pub mod rotator_controller;
+pub mod state_machine_controller;
 
 pub trait Controller : Send + Sync {
-    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>);
+    //Returning an error (or panicking) disables the controller, see ControllerSystem::run
+    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()>;
+
+    //Called before update for each message sent to this entity during the previous frame
+    fn on_message(&mut self, _index: usize, _message: &Message, _scene: Arc<RwLock<Scene>>) -> Result<()> {
+        Ok(())
+    }
+
+    //Short human readable readout for logs and inspectors
+    fn describe(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
 
     fn fixed_update(&self, _: usize, _: Scene) {}
-    fn late_update(&self, _: usize, _: Scene) {}
+
+    //Runs once every controller has updated, one entity at a time in index order. Anything
+    //read from other entities here, like a camera's target, is the same whichever update
+    //happened to run first. Errors disable the controller like update's do.
+    fn late_update(&mut self, _index: usize, _scene: Arc<RwLock<Scene>>) -> Result<()> {
+        Ok(())
+    }
 }
 
 //Implementing component for all structs that impl controller
@@ -48,6 +73,6 @@ impl Component for Arc<RwLock<Box<dyn Controller>>>{}
 
 impl Debug for dyn Controller {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Controller, output coming soon!")
+        write!(f, "{}", self.describe())
     }
 }
@@ -0,0 +1,418 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{bail, Context, Result};
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::component::mesh_renderer_component::MeshRendererComponent;
+use crate::component::transform_component::TransformComponent;
+use crate::resource::mailbox::Message;
+use crate::resource::time::Time;
+use crate::scene::lock::RecoverLock;
+use crate::scene::Scene;
+
+use super::Controller;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    Step,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+    BackOut,
+    ElasticIn,
+    ElasticOut,
+    BounceOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Easing::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+            Easing::ElasticIn => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    let c4 = (2.0 * PI) / 3.0;
+                    -(2f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * c4).sin()
+                }
+            }
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    let c4 = (2.0 * PI) / 3.0;
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * c4).sin() + 1.0
+                }
+            }
+            Easing::BounceOut => {
+                let n1 = 7.5625;
+                let d1 = 2.75;
+                if t < 1.0 / d1 {
+                    n1 * t * t
+                } else if t < 2.0 / d1 {
+                    let t = t - 1.5 / d1;
+                    n1 * t * t + 0.75
+                } else if t < 2.5 / d1 {
+                    let t = t - 2.25 / d1;
+                    n1 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / d1;
+                    n1 * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+//The easing curve is used for the segment from this keyframe to the next one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum PlaybackMode {
+    #[default]
+    Once,
+    Loop,
+    PingPong,
+}
+
+//Tracks left empty don't touch that part of the entity. Rotation keyframes are XYZ euler
+//angles in radians, interpolated with slerp so they take the shortest path between keys.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnimationClip {
+    #[serde(default)]
+    pub translation: Vec<Keyframe<Vec3>>,
+    #[serde(default)]
+    pub rotation: Vec<Keyframe<Vec3>>,
+    #[serde(default)]
+    pub scale: Vec<Keyframe<Vec3>>,
+    //Sets the entity's tint, which multiplies its vertex colours
+    #[serde(default)]
+    pub color: Vec<Keyframe<Vec3>>,
+    #[serde(default)]
+    pub mode: PlaybackMode,
+    //Number of loops or ping-pong round trips before the sequence moves on, None plays forever
+    #[serde(default)]
+    pub repeat: Option<u32>,
+}
+
+impl AnimationClip {
+    pub fn duration(&self) -> f32 {
+        [&self.translation, &self.rotation, &self.scale, &self.color]
+            .iter()
+            .filter_map(|track| track.last())
+            .map(|keyframe| keyframe.time)
+            .fold(0.0, f32::max)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, track) in [
+            ("translation", &self.translation),
+            ("rotation", &self.rotation),
+            ("scale", &self.scale),
+            ("color", &self.color),
+        ] {
+            if track.windows(2).any(|pair| pair[1].time < pair[0].time) {
+                bail!("Keyframes in the {} track are not sorted by time", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+//Clips play one after the other, e.g. loaded from a RON file like
+//(clips: [(translation: [(time: 0.0, value: (0.0, 0.0, 0.0)), (time: 2.0, value: (0.0, 5.0, 0.0), easing: ElasticOut)], mode: PingPong)])
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnimationSequence {
+    pub clips: Vec<AnimationClip>,
+    #[serde(default)]
+    pub repeat_sequence: bool,
+}
+
+impl AnimationSequence {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read animation {}", path.display()))?;
+        let sequence: AnimationSequence = ron::from_str(&text)
+            .with_context(|| format!("Could not parse animation {}", path.display()))?;
+        sequence.validate()?;
+        Ok(sequence)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (index, clip) in self.clips.iter().enumerate() {
+            clip.validate()
+                .with_context(|| format!("Animation clip {} is invalid", index))?;
+        }
+        Ok(())
+    }
+}
+
+//Send this to an entity running an AnimationController
+#[derive(Debug, Clone, Copy)]
+pub enum AnimationCommand {
+    Pause,
+    Resume,
+    Restart,
+}
+
+pub struct AnimationController {
+    sequence: AnimationSequence,
+    clip: usize,
+    time: f32,
+    forward: bool,
+    cycles: u32,
+    paused: bool,
+    finished: bool,
+}
+
+impl AnimationController {
+    pub fn new(sequence: AnimationSequence) -> Result<Self> {
+        sequence.validate()?;
+        Ok(AnimationController {
+            sequence,
+            clip: 0,
+            time: 0.0,
+            forward: true,
+            cycles: 0,
+            paused: false,
+            finished: false,
+        })
+    }
+
+    pub fn from_clip(clip: AnimationClip) -> Result<Self> {
+        AnimationController::new(AnimationSequence {
+            clips: vec![clip],
+            repeat_sequence: false,
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        AnimationController::new(AnimationSequence::from_file(path)?)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn restart(&mut self) {
+        self.clip = 0;
+        self.time = 0.0;
+        self.forward = true;
+        self.cycles = 0;
+        self.finished = false;
+    }
+
+    fn advance(&mut self, delta: f32) {
+        let Some(clip) = self.sequence.clips.get(self.clip) else {
+            self.finished = true;
+            return;
+        };
+        let duration = clip.duration();
+        if self.forward {
+            self.time += delta;
+        } else {
+            self.time -= delta;
+        }
+
+        let cycle_done = match clip.mode {
+            PlaybackMode::Once => {
+                if self.time < duration {
+                    return;
+                }
+                self.time = duration;
+                true
+            }
+            PlaybackMode::Loop => {
+                if self.time < duration {
+                    return;
+                }
+                self.time = if duration > 0.0 { self.time % duration } else { 0.0 };
+                self.cycles += 1;
+                clip.repeat.is_some_and(|repeat| self.cycles >= repeat)
+            }
+            PlaybackMode::PingPong => {
+                if self.forward && self.time >= duration {
+                    self.time = (2.0 * duration - self.time).max(0.0);
+                    self.forward = false;
+                    false
+                } else if !self.forward && self.time <= 0.0 {
+                    self.time = (-self.time).min(duration);
+                    self.forward = true;
+                    self.cycles += 1;
+                    clip.repeat.is_some_and(|repeat| self.cycles >= repeat)
+                } else {
+                    return;
+                }
+            }
+        };
+
+        if cycle_done {
+            if self.clip + 1 < self.sequence.clips.len() {
+                self.clip += 1;
+            } else if self.sequence.repeat_sequence {
+                self.clip = 0;
+            } else {
+                self.finished = true;
+                return;
+            }
+            self.time = 0.0;
+            self.forward = true;
+            self.cycles = 0;
+        }
+    }
+
+    fn apply(&self, index: usize, scene: &Scene) -> Result<()> {
+        let Some(clip) = self.sequence.clips.get(self.clip) else {
+            return Ok(());
+        };
+
+        if !(clip.translation.is_empty() && clip.rotation.is_empty() && clip.scale.is_empty()) {
+            let transforms = scene
+                .get_component_vec::<TransformComponent>()
+                .context("Scene has no transform components")?;
+            let mut transforms = transforms.write_or_recover();
+            if let Some(transform_component) = transforms[index].as_mut() {
+                let (scale, rotation, translation) =
+                    transform_component.transform.to_scale_rotation_translation();
+                let translation = sample(&clip.translation, self.time, Vec3::lerp).unwrap_or(translation);
+                let scale = sample(&clip.scale, self.time, Vec3::lerp).unwrap_or(scale);
+                //Slerped and applied as a quaternion, going back through euler angles would
+                //lose precision and flip near +-90 degrees of pitch
+                let euler = |angles: Vec3| Quat::from_euler(EulerRot::XYZ, angles.x, angles.y, angles.z);
+                let rotation = sample(&clip.rotation, self.time, |from, to, s| {
+                    euler(from).slerp(euler(to), s)
+                })
+                .unwrap_or(rotation);
+                transform_component.set_transform(Mat4::from_scale_rotation_translation(
+                    scale,
+                    rotation,
+                    translation,
+                ));
+            }
+        }
+
+        if let Some(color) = sample(&clip.color, self.time, Vec3::lerp) {
+            let mesh_renderers = scene
+                .get_component_vec::<MeshRendererComponent>()
+                .context("Scene has no mesh renderer components")?;
+            let mut mesh_renderers = mesh_renderers.write_or_recover();
+            if let Some(mesh_renderer) = mesh_renderers[index].as_mut() {
+                mesh_renderer.tint = color.extend(1.0);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Controller for AnimationController {
+    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        if self.paused || self.finished {
+            return Ok(());
+        }
+        let scene = scene.read_or_recover();
+        self.advance(Time::delta_secs_in(&scene));
+        self.apply(index, &scene)
+    }
+
+    fn on_message(&mut self, _index: usize, message: &Message, _scene: Arc<RwLock<Scene>>) -> Result<()> {
+        match message.get::<AnimationCommand>() {
+            Some(AnimationCommand::Pause) => self.paused = true,
+            Some(AnimationCommand::Resume) => self.paused = false,
+            Some(AnimationCommand::Restart) => self.restart(),
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "AnimationController(clip: {}/{}, time: {:.2}s, finished: {})",
+            self.clip + 1,
+            self.sequence.clips.len(),
+            self.time,
+            self.finished
+        )
+    }
+}
+
+//Before the first keyframe and after the last the value is held, interpolated with itself
+//so it comes out in the same form as the rest
+fn sample<T: Copy, U>(track: &[Keyframe<T>], time: f32, interpolate: impl Fn(T, T, f32) -> U) -> Option<U> {
+    let first = track.first()?;
+    if time <= first.time {
+        return Some(interpolate(first.value, first.value, 0.0));
+    }
+    let next = track.iter().position(|keyframe| keyframe.time > time);
+    match next {
+        None => track.last().map(|keyframe| interpolate(keyframe.value, keyframe.value, 0.0)),
+        Some(next) => {
+            let (from, to) = (&track[next - 1], &track[next]);
+            let s = (time - from.time) / (to.time - from.time);
+            Some(interpolate(from.value, to.value, from.easing.apply(s)))
+        }
+    }
+}
@@ -0,0 +1,624 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, bail, Context, Result};
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::component::transform_component::TransformComponent;
+use crate::resource::mailbox::Message;
+use crate::resource::time::Time;
+use crate::scene::lock::RecoverLock;
+use crate::scene::Scene;
+
+use super::Controller;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    Bool(bool),
+    Float(f32),
+    Text(String),
+    Vec3([f32; 3]),
+}
+
+pub type Blackboard = HashMap<String, Value>;
+
+//Send this to an entity running a BehaviourTreeController to write its blackboard
+#[derive(Debug, Clone)]
+pub struct SetBlackboard(pub String, pub Value);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Decorator {
+    Inverter,
+    Succeeder,
+    Repeat(u32),
+    RepeatForever,
+    UntilFailure,
+    //Fails the child if it is still running after this many seconds
+    Timeout(f32),
+}
+
+//Tree definition as loaded from a file, e.g.
+//Selector([
+//    Sequence([
+//        Action(name: "blackboard_is", params: {"key": Text("alerted"), "value": Bool(true)}),
+//        Action(name: "translate", params: {"velocity": Vec3((0.0, 0.0, 2.0)), "seconds": Float(1.0)}),
+//    ]),
+//    Decorator(Repeat(3), Action(name: "rotate", params: {"axis": Vec3((0.0, 1.0, 0.0)), "speed": Float(1.0), "seconds": Float(0.5)})),
+//])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NodeDefinition {
+    Sequence(Vec<NodeDefinition>),
+    Selector(Vec<NodeDefinition>),
+    //Succeeds once success_threshold children succeed, fails once that is no longer possible
+    Parallel {
+        success_threshold: usize,
+        children: Vec<NodeDefinition>,
+    },
+    Decorator(Decorator, Box<NodeDefinition>),
+    Action {
+        name: String,
+        #[serde(default)]
+        params: HashMap<String, Value>,
+    },
+}
+
+impl NodeDefinition {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read behaviour tree {}", path.display()))?;
+        ron::from_str(&text)
+            .with_context(|| format!("Could not parse behaviour tree {}", path.display()))
+    }
+}
+
+//Everything an action leaf can see while ticking
+pub struct ActionContext<'a> {
+    pub index: usize,
+    pub scene: &'a Arc<RwLock<Scene>>,
+    pub blackboard: &'a mut Blackboard,
+    pub delta: f32,
+}
+
+impl ActionContext<'_> {
+    pub fn with_transform<R>(&self, op: impl FnOnce(&mut TransformComponent) -> R) -> Result<R> {
+        let scene = self.scene.read_or_recover();
+        let transforms = scene
+            .get_component_vec::<TransformComponent>()
+            .context("Scene has no transform components")?;
+        let mut transforms = transforms.write_or_recover();
+        let transform = transforms
+            .get_mut(self.index)
+            .and_then(Option::as_mut)
+            .with_context(|| format!("Entity {} has no transform component", self.index))?;
+        Ok(op(transform))
+    }
+}
+
+pub trait Action: Send + Sync {
+    fn tick(&mut self, context: &mut ActionContext) -> Result<Status>;
+
+    //Called when the action finishes or its parent gives up on it
+    fn reset(&mut self) {}
+}
+
+type ActionFactory = Box<dyn Fn(&HashMap<String, Value>) -> Result<Box<dyn Action>> + Send + Sync>;
+
+//Maps the action names used in tree files to code
+pub struct ActionRegistry {
+    factories: HashMap<String, ActionFactory>,
+}
+
+impl ActionRegistry {
+    pub fn new() -> Self {
+        ActionRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    pub fn with_builtins() -> Self {
+        let mut registry = ActionRegistry::new();
+        registry.register("wait", |params| {
+            Ok(Box::new(Wait {
+                seconds: float_param(params, "seconds")?,
+                elapsed: 0.0,
+            }))
+        });
+        registry.register("translate", |params| {
+            Ok(Box::new(Translate {
+                velocity: vec3_param(params, "velocity")?,
+                seconds: float_param(params, "seconds")?,
+                elapsed: 0.0,
+            }))
+        });
+        registry.register("rotate", |params| {
+            Ok(Box::new(Rotate {
+                axis: vec3_param(params, "axis")?.normalize_or_zero(),
+                speed: float_param(params, "speed")?,
+                seconds: float_param(params, "seconds")?,
+                elapsed: 0.0,
+            }))
+        });
+        registry.register("set_blackboard", |params| {
+            Ok(Box::new(SetBlackboardAction {
+                key: text_param(params, "key")?,
+                value: param(params, "value")?.clone(),
+            }))
+        });
+        registry.register("blackboard_is", |params| {
+            Ok(Box::new(BlackboardIs {
+                key: text_param(params, "key")?,
+                value: param(params, "value")?.clone(),
+            }))
+        });
+        registry
+    }
+
+    pub fn register(
+        &mut self,
+        name: &str,
+        factory: impl Fn(&HashMap<String, Value>) -> Result<Box<dyn Action>> + Send + Sync + 'static,
+    ) {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    //For leaves that need no parameters or per instance state
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        action: impl Fn(&mut ActionContext) -> Result<Status> + Send + Sync + Clone + 'static,
+    ) {
+        self.register(name, move |_| Ok(Box::new(FnAction(action.clone()))));
+    }
+
+    fn build(&self, name: &str, params: &HashMap<String, Value>) -> Result<Box<dyn Action>> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| anyhow!("Unknown behaviour tree action \"{}\"", name))?;
+        factory(params).with_context(|| format!("Could not build action \"{}\"", name))
+    }
+}
+
+impl Default for ActionRegistry {
+    fn default() -> Self {
+        ActionRegistry::with_builtins()
+    }
+}
+
+enum Node {
+    Sequence {
+        children: Vec<Node>,
+        current: usize,
+    },
+    Selector {
+        children: Vec<Node>,
+        current: usize,
+    },
+    Parallel {
+        success_threshold: usize,
+        children: Vec<Node>,
+        finished: Vec<Option<Status>>,
+    },
+    Decorator {
+        decorator: Decorator,
+        child: Box<Node>,
+        count: u32,
+        elapsed: f32,
+    },
+    Action {
+        name: String,
+        action: Box<dyn Action>,
+    },
+}
+
+impl Node {
+    fn build(definition: &NodeDefinition, registry: &ActionRegistry) -> Result<Node> {
+        let build_all = |children: &Vec<NodeDefinition>| -> Result<Vec<Node>> {
+            children
+                .iter()
+                .map(|child| Node::build(child, registry))
+                .collect()
+        };
+        Ok(match definition {
+            NodeDefinition::Sequence(children) => Node::Sequence {
+                children: build_all(children)?,
+                current: 0,
+            },
+            NodeDefinition::Selector(children) => Node::Selector {
+                children: build_all(children)?,
+                current: 0,
+            },
+            NodeDefinition::Parallel {
+                success_threshold,
+                children,
+            } => {
+                if *success_threshold > children.len() {
+                    bail!(
+                        "Parallel success threshold {} is larger than its {} children",
+                        success_threshold,
+                        children.len()
+                    );
+                }
+                Node::Parallel {
+                    success_threshold: *success_threshold,
+                    finished: vec![None; children.len()],
+                    children: build_all(children)?,
+                }
+            }
+            NodeDefinition::Decorator(decorator, child) => Node::Decorator {
+                decorator: *decorator,
+                child: Box::new(Node::build(child, registry)?),
+                count: 0,
+                elapsed: 0.0,
+            },
+            NodeDefinition::Action { name, params } => Node::Action {
+                name: name.clone(),
+                action: registry.build(name, params)?,
+            },
+        })
+    }
+
+    fn tick(&mut self, context: &mut ActionContext, path: &mut Vec<String>) -> Result<Status> {
+        match self {
+            Node::Sequence { children, current } => {
+                while *current < children.len() {
+                    match children[*current].tick(context, path)? {
+                        Status::Success => *current += 1,
+                        Status::Running => return Ok(Status::Running),
+                        Status::Failure => {
+                            self.reset();
+                            return Ok(Status::Failure);
+                        }
+                    }
+                }
+                self.reset();
+                Ok(Status::Success)
+            }
+            Node::Selector { children, current } => {
+                while *current < children.len() {
+                    match children[*current].tick(context, path)? {
+                        Status::Failure => *current += 1,
+                        Status::Running => return Ok(Status::Running),
+                        Status::Success => {
+                            self.reset();
+                            return Ok(Status::Success);
+                        }
+                    }
+                }
+                self.reset();
+                Ok(Status::Failure)
+            }
+            Node::Parallel {
+                success_threshold,
+                children,
+                finished,
+            } => {
+                for (child, finished) in children.iter_mut().zip(finished.iter_mut()) {
+                    if finished.is_none() {
+                        match child.tick(context, path)? {
+                            Status::Running => {}
+                            status => *finished = Some(status),
+                        }
+                    }
+                }
+                let successes = finished
+                    .iter()
+                    .filter(|status| **status == Some(Status::Success))
+                    .count();
+                let failures = finished
+                    .iter()
+                    .filter(|status| **status == Some(Status::Failure))
+                    .count();
+                let status = if successes >= *success_threshold {
+                    Status::Success
+                } else if failures > children.len() - *success_threshold {
+                    Status::Failure
+                } else {
+                    return Ok(Status::Running);
+                };
+                self.reset();
+                Ok(status)
+            }
+            Node::Decorator {
+                decorator,
+                child,
+                count,
+                elapsed,
+            } => {
+                //Repeating zero times is done without running the child at all
+                if matches!(decorator, Decorator::Repeat(0)) {
+                    return Ok(Status::Success);
+                }
+                *elapsed += context.delta;
+                let child_status = child.tick(context, path)?;
+                let status = match (*decorator, child_status) {
+                    (Decorator::Timeout(seconds), Status::Running) if *elapsed > seconds => {
+                        child.reset();
+                        Status::Failure
+                    }
+                    (_, Status::Running) => return Ok(Status::Running),
+                    (Decorator::Inverter, Status::Success) => Status::Failure,
+                    (Decorator::Inverter, Status::Failure) => Status::Success,
+                    (Decorator::Succeeder, _) => Status::Success,
+                    (Decorator::Repeat(times), _) => {
+                        *count += 1;
+                        if *count < times {
+                            return Ok(Status::Running);
+                        }
+                        Status::Success
+                    }
+                    (Decorator::RepeatForever, _) => return Ok(Status::Running),
+                    (Decorator::UntilFailure, Status::Failure) => Status::Success,
+                    (Decorator::UntilFailure, _) => return Ok(Status::Running),
+                    (Decorator::Timeout(_), status) => status,
+                };
+                self.reset();
+                Ok(status)
+            }
+            Node::Action { name, action } => {
+                let status = action.tick(context)?;
+                match status {
+                    Status::Running => path.push(name.clone()),
+                    _ => action.reset(),
+                }
+                Ok(status)
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Node::Sequence { children, current } | Node::Selector { children, current } => {
+                *current = 0;
+                children.iter_mut().for_each(Node::reset);
+            }
+            Node::Parallel {
+                children, finished, ..
+            } => {
+                finished.iter_mut().for_each(|status| *status = None);
+                children.iter_mut().for_each(Node::reset);
+            }
+            Node::Decorator {
+                child,
+                count,
+                elapsed,
+                ..
+            } => {
+                *count = 0;
+                *elapsed = 0.0;
+                child.reset();
+            }
+            Node::Action { action, .. } => action.reset(),
+        }
+    }
+}
+
+//Ticks a behaviour tree once per frame, restarting it from the root when it finishes
+pub struct BehaviourTreeController {
+    root: Node,
+    blackboard: Blackboard,
+    last_status: Option<Status>,
+    //Actions that returned Running on the last tick
+    running: Vec<String>,
+}
+
+impl BehaviourTreeController {
+    pub fn new(definition: &NodeDefinition, registry: &ActionRegistry) -> Result<Self> {
+        Ok(BehaviourTreeController {
+            root: Node::build(definition, registry)?,
+            blackboard: Blackboard::new(),
+            last_status: None,
+            running: vec![],
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>, registry: &ActionRegistry) -> Result<Self> {
+        BehaviourTreeController::new(&NodeDefinition::from_file(path)?, registry)
+    }
+
+    pub fn blackboard(&self) -> &Blackboard {
+        &self.blackboard
+    }
+
+    pub fn blackboard_mut(&mut self) -> &mut Blackboard {
+        &mut self.blackboard
+    }
+
+    pub fn last_status(&self) -> Option<Status> {
+        self.last_status
+    }
+}
+
+impl Controller for BehaviourTreeController {
+    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        let delta = Time::delta_secs_in(&scene.read_or_recover());
+        let mut context = ActionContext {
+            index,
+            scene: &scene,
+            blackboard: &mut self.blackboard,
+            delta,
+        };
+        self.running.clear();
+        self.last_status = Some(self.root.tick(&mut context, &mut self.running)?);
+        Ok(())
+    }
+
+    fn on_message(&mut self, _index: usize, message: &Message, _scene: Arc<RwLock<Scene>>) -> Result<()> {
+        if let Some(SetBlackboard(key, value)) = message.get::<SetBlackboard>() {
+            self.blackboard.insert(key.clone(), value.clone());
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "BehaviourTreeController(status: {:?}, running: [{}])",
+            self.last_status,
+            self.running.join(", ")
+        )
+    }
+}
+
+fn param<'a>(params: &'a HashMap<String, Value>, key: &str) -> Result<&'a Value> {
+    params
+        .get(key)
+        .ok_or_else(|| anyhow!("Missing parameter \"{}\"", key))
+}
+
+fn float_param(params: &HashMap<String, Value>, key: &str) -> Result<f32> {
+    match param(params, key)? {
+        Value::Float(value) => Ok(*value),
+        other => bail!("Parameter \"{}\" should be a Float, got {:?}", key, other),
+    }
+}
+
+fn vec3_param(params: &HashMap<String, Value>, key: &str) -> Result<Vec3> {
+    match param(params, key)? {
+        Value::Vec3(value) => Ok(Vec3::from_array(*value)),
+        other => bail!("Parameter \"{}\" should be a Vec3, got {:?}", key, other),
+    }
+}
+
+fn text_param(params: &HashMap<String, Value>, key: &str) -> Result<String> {
+    match param(params, key)? {
+        Value::Text(value) => Ok(value.clone()),
+        other => bail!("Parameter \"{}\" should be Text, got {:?}", key, other),
+    }
+}
+
+struct FnAction<F>(F);
+
+impl<F> Action for FnAction<F>
+where
+    F: Fn(&mut ActionContext) -> Result<Status> + Send + Sync,
+{
+    fn tick(&mut self, context: &mut ActionContext) -> Result<Status> {
+        (self.0)(context)
+    }
+}
+
+struct Wait {
+    seconds: f32,
+    elapsed: f32,
+}
+
+impl Action for Wait {
+    fn tick(&mut self, context: &mut ActionContext) -> Result<Status> {
+        self.elapsed += context.delta;
+        if self.elapsed >= self.seconds {
+            Ok(Status::Success)
+        } else {
+            Ok(Status::Running)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+struct Translate {
+    velocity: Vec3,
+    seconds: f32,
+    elapsed: f32,
+}
+
+impl Action for Translate {
+    fn tick(&mut self, context: &mut ActionContext) -> Result<Status> {
+        let step = context.delta.min(self.seconds - self.elapsed).max(0.0);
+        let translation = Mat4::from_translation(self.velocity * step);
+        context.with_transform(|transform| {
+            transform.transform_op(|transform| translation * transform)
+        })?;
+        self.elapsed += step;
+        if self.elapsed >= self.seconds {
+            Ok(Status::Success)
+        } else {
+            Ok(Status::Running)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+struct Rotate {
+    axis: Vec3,
+    speed: f32,
+    seconds: f32,
+    elapsed: f32,
+}
+
+impl Action for Rotate {
+    fn tick(&mut self, context: &mut ActionContext) -> Result<Status> {
+        let step = context.delta.min(self.seconds - self.elapsed).max(0.0);
+        let rotation = Mat4::from_axis_angle(self.axis, self.speed * step);
+        context.with_transform(|transform| transform.transform_op(|transform| transform * rotation))?;
+        self.elapsed += step;
+        if self.elapsed >= self.seconds {
+            Ok(Status::Success)
+        } else {
+            Ok(Status::Running)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+struct SetBlackboardAction {
+    key: String,
+    value: Value,
+}
+
+impl Action for SetBlackboardAction {
+    fn tick(&mut self, context: &mut ActionContext) -> Result<Status> {
+        context
+            .blackboard
+            .insert(self.key.clone(), self.value.clone());
+        Ok(Status::Success)
+    }
+}
+
+struct BlackboardIs {
+    key: String,
+    value: Value,
+}
+
+impl Action for BlackboardIs {
+    fn tick(&mut self, context: &mut ActionContext) -> Result<Status> {
+        if context.blackboard.get(&self.key) == Some(&self.value) {
+            Ok(Status::Success)
+        } else {
+            Ok(Status::Failure)
+        }
+    }
+}
@@ -1,12 +1,16 @@
 use std::sync::{Arc, RwLock};
-use std::time::Instant;
 
-use glam::{Mat4, Vec3};
+use anyhow::{Context, Result};
+use glam::{Mat4, Vec3, Vec4};
 use rand::Rng;
 use std::fmt::Debug;
 
 use crate::component::mesh_filter_component::MeshFilterComponent;
+use crate::component::mesh_renderer_component::MeshRendererComponent;
 use crate::component::transform_component::TransformComponent;
+use crate::resource::random::Random;
+use crate::resource::time::Time;
+use crate::scene::lock::RecoverLock;
 use crate::scene::Scene;
 
 use super::Controller;
@@ -14,49 +18,76 @@ use super::Controller;
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct ColorController {
-    last_update: Instant,
+    since_update: f32,
 }
 impl ColorController {
     pub fn new() -> Self {
-        ColorController {
-            last_update: Instant::now(),
+        ColorController { since_update: 0.0 }
+    }
+
+    //Colour goes through the entity's tint so its material stays shared, only the vertex
+    //positions are rewritten
+    fn recolor(&self, index: usize, scene: &Scene, color: Vec4) -> Result<()> {
+        let mesh_renderers = scene
+            .get_component_vec::<MeshRendererComponent>()
+            .context("Scene has no mesh renderer components")?;
+        let mut mesh_renderers = mesh_renderers.write_or_recover();
+        if let Some(mesh_renderer) = mesh_renderers.get_mut(index).and_then(Option::as_mut) {
+            mesh_renderer.tint = color;
         }
+        Ok(())
     }
 }
 
 impl Controller for ColorController {
-    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) {
+    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
         //info!("Color update");
-        let scene = scene.read().unwrap();
-        let mut rng = rand::thread_rng();
-        let now = Instant::now();
-        let elapsed = now.saturating_duration_since(self.last_update);
+        let scene = scene.read_or_recover();
+        let (delta, frame) = scene.get_resource::<Time>().map_or((0.0, 0), |time| {
+            let time = time.read_or_recover();
+            (time.delta_secs(), time.frame())
+        });
+        //Seeded per entity and frame so replays see the same colours, there is no unseeded fallback
+        let mut rng = scene
+            .get_resource::<Random>()
+            .context("Scene has no Random resource to jitter from")?
+            .read_or_recover()
+            .entity_rng(index, frame);
+        self.since_update += delta;
         //println!("Prior to lock");
         //println!("After lock");
-        if elapsed.as_secs_f64() > 0.01 {
+        if self.since_update > 0.01 {
             //println!("Elapsed > 2");
              
-            let mesh_filter_components = scene.get_component_vec::<MeshFilterComponent>().unwrap();
-            let mut mesh_filter_components = mesh_filter_components.write().unwrap();
+            let mesh_filter_components = scene
+                .get_component_vec::<MeshFilterComponent>()
+                .context("Scene has no mesh filter components")?;
+            let mut mesh_filter_components = mesh_filter_components.write_or_recover();
 
             if let Some(my_mesh_filter) = mesh_filter_components[index].as_mut() {
-                for vertex in &mut my_mesh_filter.indexed_verts.verts {
-                    vertex.color = Vec3 {
-                        x: rng.gen_range(0..=1) as f32,
-                        y: rng.gen_range(0..1) as f32,
-                        z: rng.gen_range(0..1) as f32,
-                    };
-                    vertex.position = Vec3 {
-                        x: vertex.position.x + rng.gen_range(-0.01..0.01),
-                        y: vertex.position.y + rng.gen_range(-0.01..0.01),
-                        z: vertex.position.z + rng.gen_range(-0.01..0.01),
+                for position in my_mesh_filter.mesh_mut().positions_mut() {
+                    *position = Vec3 {
+                        x: position.x + rng.gen_range(-0.01..0.01),
+                        y: position.y + rng.gen_range(-0.01..0.01),
+                        z: position.z + rng.gen_range(-0.01..0.01),
                     }
                 }
-                self.last_update = now;
+                self.since_update = 0.0;
             }
-            let transform_components = scene.get_component_vec::<TransformComponent>().unwrap();
+            drop(mesh_filter_components);
+            let color = Vec4::new(
+                rng.gen_range(0.0..=1.0),
+                rng.gen_range(0.0..=1.0),
+                rng.gen_range(0.0..=1.0),
+                1.0,
+            );
+            self.recolor(index, &scene, color)?;
+
+            let transform_components = scene
+                .get_component_vec::<TransformComponent>()
+                .context("Scene has no transform components")?;
 
-            let mut transform_components = transform_components.write().unwrap();
+            let mut transform_components = transform_components.write_or_recover();
             if let Some(transform_component) = transform_components[index].as_mut() {
                 let rot1 = Mat4::from_axis_angle(Vec3::Y, 0.02);
                 transform_component.transform_op(|transform| transform * rot1);
@@ -86,7 +117,8 @@ impl Controller for ColorController {
                     ]
                 }
             );*/
-            self.last_update = now;
+            self.since_update = 0.0;
         }
+        Ok(())
     }
 }
@@ -0,0 +1,170 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::f32::consts::FRAC_PI_2;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use glam::{Mat4, Vec2, Vec3, Vec4Swizzles};
+
+use crate::component::camera_component::CameraComponent;
+use crate::component::transform_component::TransformComponent;
+use crate::resource::input_state::InputState;
+use crate::resource::time::Time;
+use crate::scene::lock::RecoverLock;
+use crate::scene::Scene;
+
+use super::Controller;
+
+//Keeps pitch just short of straight up/down so look_to never degenerates
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+const MIN_FOVY: f32 = 0.1;
+const MAX_FOVY: f32 = 2.5;
+
+//Free flying camera driven by the move_forward/back/left/right/up/down actions, the
+//look_x/look_y axes while the cursor is grabbed and the zoom axis for field of view.
+//Yaw and pitch are tracked separately and the view rebuilt each frame so roll never builds up.
+#[derive(Debug, Clone)]
+pub struct FlyCameraController {
+    //Units per second
+    pub move_speed: f32,
+    //Radians per unit of the look axes, pixels with the default bindings
+    pub look_sensitivity: f32,
+    pub invert_y: bool,
+    //Radians of vertical field of view per unit of the zoom axis
+    pub zoom_speed: f32,
+    position: Vec3,
+    yaw: f32,
+    pitch: f32,
+    initialised: bool,
+}
+
+impl FlyCameraController {
+    pub fn new() -> Self {
+        FlyCameraController {
+            move_speed: 12.0,
+            look_sensitivity: 0.005,
+            invert_y: false,
+            zoom_speed: 0.05,
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            initialised: false,
+        }
+    }
+
+    pub fn with_move_speed(mut self, move_speed: f32) -> Self {
+        self.move_speed = move_speed;
+        self
+    }
+
+    pub fn with_zoom_speed(mut self, zoom_speed: f32) -> Self {
+        self.zoom_speed = zoom_speed;
+        self
+    }
+
+    pub fn with_look_sensitivity(mut self, look_sensitivity: f32) -> Self {
+        self.look_sensitivity = look_sensitivity;
+        self
+    }
+
+    fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+}
+
+impl Controller for FlyCameraController {
+    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        let scene = scene.read_or_recover();
+        let delta = Time::delta_secs_in(&scene);
+        //x is right, y is up and z is forward relative to where the camera faces
+        let (look, zoom, intent) = match scene.get_resource::<InputState>() {
+            Some(input) => {
+                let input = input.read_or_recover();
+                //Only mouse look while the cursor is grabbed, otherwise the user is pointing at something
+                let look = if input.cursor_grabbed() {
+                    Vec2::new(input.axis("look_x"), input.axis("look_y"))
+                } else {
+                    Vec2::ZERO
+                };
+                (
+                    look,
+                    input.axis("zoom"),
+                    Vec3::new(
+                        input.action_axis("move_right", "move_left"),
+                        input.action_axis("move_up", "move_down"),
+                        input.action_axis("move_forward", "move_back"),
+                    ),
+                )
+            }
+            None => (Vec2::ZERO, 0.0, Vec3::ZERO),
+        };
+
+        if zoom != 0.0 {
+            let cameras = scene
+                .get_component_vec::<CameraComponent>()
+                .context("Scene has no camera components")?;
+            if let Some(camera) = cameras.write_or_recover()[index].as_mut() {
+                camera.set_fovy((camera.fovy - zoom * self.zoom_speed).clamp(MIN_FOVY, MAX_FOVY));
+            }
+        }
+
+        let transforms = scene
+            .get_component_vec::<TransformComponent>()
+            .context("Scene has no transform components")?;
+        let mut transforms = transforms.write_or_recover();
+        let transform = transforms[index]
+            .as_mut()
+            .context("Fly camera entity has no transform component")?;
+
+        //Camera transforms hold the view matrix, pick up wherever the scene placed the camera
+        if !self.initialised {
+            let world = transform.transform.inverse();
+            let forward = world.z_axis.xyz().normalize_or_zero();
+            self.position = world.w_axis.xyz();
+            self.yaw = forward.x.atan2(forward.z);
+            self.pitch = forward.y.clamp(-1.0, 1.0).asin().clamp(-MAX_PITCH, MAX_PITCH);
+            self.initialised = true;
+        }
+
+        let pitch_delta = look.y * self.look_sensitivity;
+        self.yaw += look.x * self.look_sensitivity;
+        self.pitch = if self.invert_y {
+            self.pitch + pitch_delta
+        } else {
+            self.pitch - pitch_delta
+        }
+        .clamp(-MAX_PITCH, MAX_PITCH);
+
+        let forward = self.forward();
+        let right = Vec3::Y.cross(forward).normalize_or_zero();
+        let movement = right * intent.x + Vec3::Y * intent.y + forward * intent.z;
+        self.position += movement.normalize_or_zero() * self.move_speed * delta;
+
+        transform.set_transform(Mat4::look_to_lh(self.position, forward, Vec3::Y));
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "FlyCameraController(position: {:?}, yaw: {:.2}, pitch: {:.2})",
+            self.position, self.yaw, self.pitch
+        )
+    }
+}
@@ -0,0 +1,101 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use glam::{Mat4, Vec3};
+
+use crate::component::transform_component::TransformComponent;
+use crate::resource::time::Time;
+use crate::scene::lock::RecoverLock;
+use crate::scene::Scene;
+
+use super::Controller;
+
+//Trails a target entity at an offset in the target's local space, easing towards it
+#[derive(Debug, Clone)]
+pub struct FollowCameraController {
+    pub target: usize,
+    pub offset: Vec3,
+    //How quickly the camera catches up, higher is snappier. Roughly 1/seconds to close most of the gap
+    pub smoothing: f32,
+    eye: Option<Vec3>,
+    look_at: Option<Vec3>,
+}
+
+impl FollowCameraController {
+    pub fn new(target: usize, offset: Vec3) -> Self {
+        FollowCameraController {
+            target,
+            offset,
+            smoothing: 5.0,
+            eye: None,
+            look_at: None,
+        }
+    }
+
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+}
+
+impl Controller for FollowCameraController {
+    fn update(&mut self, _index: usize, _scene: Arc<RwLock<Scene>>) -> Result<()> {
+        Ok(())
+    }
+
+    //Late so the target has already moved this frame, whichever worker moved it
+    fn late_update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        let scene = scene.read_or_recover();
+        let delta = Time::delta_secs_in(&scene);
+
+        let transforms = scene
+            .get_component_vec::<TransformComponent>()
+            .context("Scene has no transform components")?;
+        let mut transforms = transforms.write_or_recover();
+        let target = transforms
+            .get(self.target)
+            .and_then(Option::as_ref)
+            .with_context(|| format!("Follow target {} has no transform component", self.target))?;
+        let target_position = target.translation();
+        let desired_eye = target.transform.transform_point3(self.offset);
+
+        //Frame rate independent exponential smoothing
+        let blend = 1.0 - (-self.smoothing * delta).exp();
+        let eye = self
+            .eye
+            .map_or(desired_eye, |eye| eye.lerp(desired_eye, blend));
+        let look_at = self
+            .look_at
+            .map_or(target_position, |look_at| look_at.lerp(target_position, blend));
+        self.eye = Some(eye);
+        self.look_at = Some(look_at);
+
+        transforms[index]
+            .as_mut()
+            .context("Follow camera entity has no transform component")?
+            .set_transform(Mat4::look_at_lh(eye, look_at, Vec3::Y));
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "FollowCameraController(target: {}, eye: {:?})",
+            self.target, self.eye
+        )
+    }
+}
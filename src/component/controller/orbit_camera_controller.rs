@@ -0,0 +1,134 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::f32::consts::FRAC_PI_2;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::component::transform_component::TransformComponent;
+use crate::resource::input_state::InputState;
+use crate::scene::lock::RecoverLock;
+use crate::scene::Scene;
+
+use super::Controller;
+
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+//Circles a target entity, the look_x/look_y axes orbit while the cursor is grabbed and the
+//zoom axis moves in and out
+#[derive(Debug, Clone)]
+pub struct OrbitCameraController {
+    pub target: usize,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    //Radians per unit of the look axes, pixels with the default bindings
+    pub orbit_sensitivity: f32,
+    //Fraction of the current distance per unit of the zoom axis, scroll lines by default
+    pub zoom_speed: f32,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl OrbitCameraController {
+    pub fn new(target: usize, distance: f32) -> Self {
+        OrbitCameraController {
+            target,
+            distance,
+            min_distance: 1.0,
+            max_distance: 1000.0,
+            orbit_sensitivity: 0.005,
+            zoom_speed: 0.1,
+            yaw: 0.0,
+            pitch: 0.3,
+        }
+    }
+
+    pub fn with_distance_limits(mut self, min_distance: f32, max_distance: f32) -> Self {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn with_orbit_sensitivity(mut self, orbit_sensitivity: f32) -> Self {
+        self.orbit_sensitivity = orbit_sensitivity;
+        self
+    }
+
+    pub fn with_zoom_speed(mut self, zoom_speed: f32) -> Self {
+        self.zoom_speed = zoom_speed;
+        self
+    }
+}
+
+impl Controller for OrbitCameraController {
+    fn update(&mut self, _index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        let scene = scene.read_or_recover();
+        let (look, zoom) = match scene.get_resource::<InputState>() {
+            Some(input) => {
+                let input = input.read_or_recover();
+                let look = if input.cursor_grabbed() {
+                    Vec2::new(input.axis("look_x"), input.axis("look_y"))
+                } else {
+                    Vec2::ZERO
+                };
+                (look, input.axis("zoom"))
+            }
+            None => (Vec2::ZERO, 0.0),
+        };
+
+        self.yaw += look.x * self.orbit_sensitivity;
+        self.pitch = (self.pitch + look.y * self.orbit_sensitivity)
+            .clamp(-MAX_PITCH, MAX_PITCH);
+        self.distance = (self.distance * (1.0 - self.zoom_speed * zoom))
+            .clamp(self.min_distance, self.max_distance);
+        Ok(())
+    }
+
+    //Placed after every update so the target's transform is this frame's, not a racing write
+    fn late_update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        let scene = scene.read_or_recover();
+        let transforms = scene
+            .get_component_vec::<TransformComponent>()
+            .context("Scene has no transform components")?;
+        let mut transforms = transforms.write_or_recover();
+        let target = transforms
+            .get(self.target)
+            .and_then(Option::as_ref)
+            .with_context(|| format!("Orbit target {} has no transform component", self.target))?
+            .translation();
+
+        let forward = Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            -self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        let eye = target - forward * self.distance;
+        transforms[index]
+            .as_mut()
+            .context("Orbit camera entity has no transform component")?
+            .set_transform(Mat4::look_at_lh(eye, target, Vec3::Y));
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "OrbitCameraController(target: {}, distance: {:.2})",
+            self.target, self.distance
+        )
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::{Arc, RwLock};
-use std::time::Instant;
 
+use anyhow::{Context, Result};
 use glam::{Mat4, Vec3};
 use rand::Rng;
 use tracing::info;
@@ -8,6 +8,7 @@ use std::fmt::Debug;
 
 use crate::component::transform_component::TransformComponent;
 use crate::prefabs::cube111::make_111_cube;
+use crate::scene::lock::RecoverLock;
 use crate::scene::Scene;
 
 
@@ -15,28 +16,21 @@ use super::Controller;
 
 #[derive(Debug, Clone)]
 #[repr(C)]
-pub struct RotatorController {
-    last_update: Instant,
-}
+pub struct RotatorController {}
 impl RotatorController {
     pub fn new() -> Self {
-        RotatorController {
-            last_update: Instant::now(),
-        }
+        RotatorController {}
     }
 }
 
 impl Controller for RotatorController {
-    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) {
-        let scene_lock = scene.read().unwrap();
-        let mut rng = rand::thread_rng();
-        let now = Instant::now();
-        let elapsed = now.saturating_duration_since(self.last_update);
+    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        let scene_lock = scene.read_or_recover();
 
         let transform_components = scene_lock
             .get_component_vec::<TransformComponent>()
-            .unwrap();
-        let mut transform_components = transform_components.write().unwrap();
+            .context("Scene has no transform components")?;
+        let mut transform_components = transform_components.write_or_recover();
         if let Some(transform_component) = transform_components[index].as_mut() {
             let rot1 = Mat4::from_axis_angle(Vec3::X, 0.02);
             transform_component.transform_op(|transform| transform * rot1);
@@ -69,5 +63,6 @@ impl Controller for RotatorController {
         }
         */
         //info!("End of update");
+        Ok(())
     }
 }
@@ -0,0 +1,280 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use tracing::debug;
+
+use crate::resource::mailbox::Message;
+use crate::resource::time::Time;
+use crate::scene::lock::RecoverLock;
+use crate::scene::Scene;
+
+use super::Controller;
+
+//Passed to every state callback and transition guard
+pub struct StateContext<'a> {
+    pub index: usize,
+    pub scene: &'a Arc<RwLock<Scene>>,
+    pub delta: f32,
+    pub time_in_state: f32,
+}
+
+//Send this to an entity running a StateMachineController<S> to force a transition next frame
+#[derive(Debug, Clone)]
+pub struct ChangeState<S>(pub S);
+
+type StateCallback<D> = Box<dyn FnMut(&mut D, &StateContext) -> Result<()> + Send + Sync>;
+type TransitionGuard<D> = Box<dyn Fn(&D, &StateContext) -> bool + Send + Sync>;
+
+struct StateCallbacks<D> {
+    on_enter: Option<StateCallback<D>>,
+    on_update: Option<StateCallback<D>>,
+    on_exit: Option<StateCallback<D>>,
+}
+
+impl<D> Default for StateCallbacks<D> {
+    fn default() -> Self {
+        StateCallbacks {
+            on_enter: None,
+            on_update: None,
+            on_exit: None,
+        }
+    }
+}
+
+struct Transition<S, D> {
+    //None matches any state
+    from: Option<S>,
+    to: S,
+    guard: TransitionGuard<D>,
+}
+
+//Generic finite state machine, D is per entity data handed to every callback
+pub struct StateMachineController<S, D = ()> {
+    current: S,
+    entered: bool,
+    time_in_state: f32,
+    forced: Option<S>,
+    data: D,
+    states: HashMap<S, StateCallbacks<D>>,
+    transitions: Vec<Transition<S, D>>,
+}
+
+impl<S> StateMachineController<S, ()>
+where
+    S: Clone + Debug + Eq + Hash + Send + Sync + 'static,
+{
+    pub fn new(initial: S) -> Self {
+        StateMachineController::with_data(initial, ())
+    }
+}
+
+impl<S, D> StateMachineController<S, D>
+where
+    S: Clone + Debug + Eq + Hash + Send + Sync + 'static,
+    D: Send + Sync + 'static,
+{
+    pub fn with_data(initial: S, data: D) -> Self {
+        StateMachineController {
+            current: initial,
+            entered: false,
+            time_in_state: 0.0,
+            forced: None,
+            data,
+            states: HashMap::new(),
+            transitions: vec![],
+        }
+    }
+
+    pub fn on_enter(
+        mut self,
+        state: S,
+        callback: impl FnMut(&mut D, &StateContext) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.states.entry(state).or_default().on_enter = Some(Box::new(callback));
+        self
+    }
+
+    pub fn on_update(
+        mut self,
+        state: S,
+        callback: impl FnMut(&mut D, &StateContext) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.states.entry(state).or_default().on_update = Some(Box::new(callback));
+        self
+    }
+
+    pub fn on_exit(
+        mut self,
+        state: S,
+        callback: impl FnMut(&mut D, &StateContext) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.states.entry(state).or_default().on_exit = Some(Box::new(callback));
+        self
+    }
+
+    //Guards are checked in the order they were added, the first passing one wins
+    pub fn transition(
+        mut self,
+        from: S,
+        to: S,
+        guard: impl Fn(&D, &StateContext) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.transitions.push(Transition {
+            from: Some(from),
+            to,
+            guard: Box::new(guard),
+        });
+        self
+    }
+
+    pub fn transition_from_any(
+        mut self,
+        to: S,
+        guard: impl Fn(&D, &StateContext) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.transitions.push(Transition {
+            from: None,
+            to,
+            guard: Box::new(guard),
+        });
+        self
+    }
+
+    pub fn current_state(&self) -> &S {
+        &self.current
+    }
+
+    pub fn time_in_state(&self) -> f32 {
+        self.time_in_state
+    }
+
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut D {
+        &mut self.data
+    }
+
+    fn call(&mut self, state: &S, context: &StateContext, pick: fn(&mut StateCallbacks<D>) -> &mut Option<StateCallback<D>>) -> Result<()> {
+        if let Some(callbacks) = self.states.get_mut(state) {
+            if let Some(callback) = pick(callbacks) {
+                return callback(&mut self.data, context);
+            }
+        }
+        Ok(())
+    }
+
+    fn change_state(&mut self, to: S, index: usize, scene: &Arc<RwLock<Scene>>, delta: f32) -> Result<()> {
+        let from = self.current.clone();
+        debug!(entity = index, "State transition {:?} -> {:?}", from, to);
+        let context = StateContext {
+            index,
+            scene,
+            delta,
+            time_in_state: self.time_in_state,
+        };
+        self.call(&from, &context, |callbacks| &mut callbacks.on_exit)?;
+
+        self.current = to.clone();
+        self.time_in_state = 0.0;
+        let context = StateContext {
+            index,
+            scene,
+            delta,
+            time_in_state: 0.0,
+        };
+        self.call(&to, &context, |callbacks| &mut callbacks.on_enter)
+    }
+}
+
+impl<S, D> Controller for StateMachineController<S, D>
+where
+    S: Clone + Debug + Eq + Hash + Send + Sync + 'static,
+    D: Send + Sync + 'static,
+{
+    fn update(&mut self, index: usize, scene: Arc<RwLock<Scene>>) -> Result<()> {
+        let delta = Time::delta_secs_in(&scene.read_or_recover());
+
+        if !self.entered {
+            self.entered = true;
+            let initial = self.current.clone();
+            let context = StateContext {
+                index,
+                scene: &scene,
+                delta,
+                time_in_state: 0.0,
+            };
+            self.call(&initial, &context, |callbacks| &mut callbacks.on_enter)?;
+        }
+
+        let next = match self.forced.take() {
+            Some(forced) => Some(forced),
+            None => {
+                let context = StateContext {
+                    index,
+                    scene: &scene,
+                    delta,
+                    time_in_state: self.time_in_state,
+                };
+                self.transitions
+                    .iter()
+                    .filter(|transition| transition.to != self.current)
+                    .filter(|transition| {
+                        transition
+                            .from
+                            .as_ref()
+                            .map_or(true, |from| *from == self.current)
+                    })
+                    .find(|transition| (transition.guard)(&self.data, &context))
+                    .map(|transition| transition.to.clone())
+            }
+        };
+        if let Some(next) = next {
+            self.change_state(next, index, &scene, delta)?;
+        }
+
+        let current = self.current.clone();
+        let context = StateContext {
+            index,
+            scene: &scene,
+            delta,
+            time_in_state: self.time_in_state,
+        };
+        self.call(&current, &context, |callbacks| &mut callbacks.on_update)?;
+        self.time_in_state += delta;
+        Ok(())
+    }
+
+    fn on_message(&mut self, _index: usize, message: &Message, _scene: Arc<RwLock<Scene>>) -> Result<()> {
+        if let Some(ChangeState(state)) = message.get::<ChangeState<S>>() {
+            self.forced = Some(state.clone());
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "StateMachineController(state: {:?}, {:.2}s in state)",
+            self.current, self.time_in_state
+        )
+    }
+}
@@ -0,0 +1,59 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use glam::{Mat4, Vec4};
+
+use super::Component;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instance {
+    //Relative to the entity's TransformComponent
+    pub transform: Mat4,
+    pub tint: Vec4,
+}
+
+impl Instance {
+    pub fn new(transform: Mat4) -> Self {
+        Instance {
+            transform,
+            tint: Vec4::ONE,
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Vec4) -> Self {
+        self.tint = tint;
+        self
+    }
+}
+
+//Draws the entity's mesh once per instance, for crowds of objects that don't need to be
+//entities of their own. Entities without one draw a single instance. All instances of a
+//mesh with the same pipeline go out in one instanced draw either way.
+#[derive(Debug, Clone, Default)]
+pub struct InstancedMesh {
+    pub instances: Vec<Instance>,
+}
+
+impl InstancedMesh {
+    pub fn new(instances: Vec<Instance>) -> Self {
+        InstancedMesh { instances }
+    }
+
+    pub fn push(&mut self, instance: Instance) {
+        self.instances.push(instance);
+    }
+}
+
+impl Component for InstancedMesh {}
@@ -0,0 +1,94 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use glam::Vec3;
+
+use super::Component;
+
+//Lights are positioned and aimed by their entity's TransformComponent. Directions are in the
+//entity's local space, the way the light points before the transform is applied.
+
+//Sunlight, lights everything from one direction
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        DirectionalLight {
+            direction,
+            color,
+            intensity,
+        }
+    }
+}
+
+impl Component for DirectionalLight {}
+
+//Shines in every direction from the entity, fading out to nothing at range
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(color: Vec3, intensity: f32, range: f32) -> Self {
+        PointLight {
+            color,
+            intensity,
+            range,
+        }
+    }
+}
+
+impl Component for PointLight {}
+
+//A point light limited to a cone, full strength inside inner_angle and fading out to
+//outer_angle. Angles are in radians from the cone's axis.
+#[derive(Debug, Clone, Copy)]
+pub struct SpotLight {
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl SpotLight {
+    pub fn new(direction: Vec3, color: Vec3, intensity: f32, range: f32) -> Self {
+        SpotLight {
+            direction,
+            color,
+            intensity,
+            range,
+            inner_angle: 0.3,
+            outer_angle: 0.5,
+        }
+    }
+
+    pub fn with_angles(mut self, inner_angle: f32, outer_angle: f32) -> Self {
+        self.inner_angle = inner_angle;
+        self.outer_angle = outer_angle.max(inner_angle);
+        self
+    }
+}
+
+impl Component for SpotLight {}
@@ -12,22 +12,458 @@
 //See the License for the specific language governing permissions and
 //limitations under the License.
 
-use crate::geometry::vertex::PositionColorNormal;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::geometry::{
+    mesh::{Indices, Mesh, VertexAttribute, VertexStream},
+    vertex::PositionColorNormal,
+};
 
 //use vulkano::pipeline::graphics::vertex_input::Vertex as VulkanVertex;
 
 use super::Component;
+
+static NEXT_MESH_ID: AtomicU64 = AtomicU64::new(0);
+
+//Names one version of a mesh's contents, the renderer caches GPU buffers by it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(u64);
+
+impl MeshId {
+    fn next() -> Self {
+        MeshId(NEXT_MESH_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+//Clones share the mesh and its id, so entities cloned from one filter share GPU buffers.
+//Editing through mesh_mut copies the mesh if it's shared and gives it a new id.
 #[derive(Debug, Clone)]
 pub struct MeshFilterComponent {
-    pub indexed_verts: IndexedPositionColorNormal,
+    mesh: Arc<Mesh>,
+    id: MeshId,
+}
+
+impl MeshFilterComponent {
+    pub fn new(mesh: impl Into<Mesh>) -> Self {
+        MeshFilterComponent {
+            mesh: Arc::new(mesh.into()),
+            id: MeshId::next(),
+        }
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    //The renderer re-uploads the mesh the next frame it's drawn
+    pub fn mesh_mut(&mut self) -> &mut Mesh {
+        self.id = MeshId::next();
+        Arc::make_mut(&mut self.mesh)
+    }
+
+    pub fn id(&self) -> MeshId {
+        self.id
+    }
 }
 
+//Interleaved vertices for building meshes and generating their normals and tangents, turned
+//into a Mesh's streams when given to a MeshFilterComponent
 #[derive(Debug, Clone)]
 pub struct IndexedPositionColorNormal {
     //Goes in vert buffer
     pub verts: Vec<PositionColorNormal>,
     //Goes in index buffer
     pub indices: Vec<u32>,
+    //One per vert, or empty for meshes that aren't textured
+    pub uvs: Vec<Vec2>,
+    //One per vert once generate_tangents has been called, see VertexAttribute::Tangent
+    pub tangents: Vec<Vec4>,
+}
+
+impl IndexedPositionColorNormal {
+    pub fn new(verts: Vec<PositionColorNormal>, indices: Vec<u32>) -> Self {
+        IndexedPositionColorNormal {
+            verts,
+            indices,
+            uvs: vec![],
+            tangents: vec![],
+        }
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<Vec2>) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    //Uvs that don't match the verts one to one are ignored
+    pub fn has_uvs(&self) -> bool {
+        !self.uvs.is_empty() && self.uvs.len() == self.verts.len()
+    }
+
+    //Tangents along increasing u for normal mapping, perpendicular to the current normals.
+    //Does nothing without uvs. The normal generators below redo them if they've been made.
+    pub fn generate_tangents(&mut self) {
+        if !self.has_uvs() {
+            return;
+        }
+        let mut tangents = vec![Vec3::ZERO; self.verts.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.verts.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| index as usize);
+            let edge_ab = self.verts[b].position - self.verts[a].position;
+            let edge_ac = self.verts[c].position - self.verts[a].position;
+            let uv_ab = self.uvs[b] - self.uvs[a];
+            let uv_ac = self.uvs[c] - self.uvs[a];
+            let determinant = uv_ab.x * uv_ac.y - uv_ac.x * uv_ab.y;
+            //A triangle with no area in uv space can't orient a tangent
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge_ab * uv_ac.y - edge_ac * uv_ab.y) / determinant;
+            let bitangent = (edge_ac * uv_ab.x - edge_ab * uv_ac.x) / determinant;
+            for vertex in [a, b, c] {
+                tangents[vertex] += tangent;
+                bitangents[vertex] += bitangent;
+            }
+        }
+        self.tangents = self
+            .verts
+            .iter()
+            .zip(tangents.into_iter().zip(bitangents))
+            .map(|(vert, (tangent, bitangent))| {
+                let tangent = (tangent - vert.normal * vert.normal.dot(tangent)).normalize_or_zero();
+                let handedness = if vert.normal.cross(tangent).dot(bitangent) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                tangent.extend(handedness)
+            })
+            .collect();
+    }
+
+    //Appends a copy of vertex with its uv and tangent, returning the copy's index
+    fn duplicate_vertex(&mut self, vertex: u32) -> u32 {
+        let vertex = vertex as usize;
+        self.verts.push(self.verts[vertex]);
+        if let Some(uv) = self.uvs.get(vertex).copied() {
+            self.uvs.push(uv);
+        }
+        if let Some(tangent) = self.tangents.get(vertex).copied() {
+            self.tangents.push(tangent);
+        }
+        (self.verts.len() - 1) as u32
+    }
+
+    fn refresh_tangents(&mut self) {
+        if !self.tangents.is_empty() {
+            self.generate_tangents();
+        }
+    }
+}
+
+//Normals generated by split_normals within this of each other share a vertex
+const NORMAL_EPSILON: f32 = 1e-4;
+
+//One vertex of one triangle
+struct Corner {
+    //Position in the index buffer
+    index: usize,
+    vertex: u32,
+    face_normal: Vec3,
+    //Angle between the triangle's two edges at this corner, weights smooth normals
+    angle: f32,
+}
+
+//Normal generation treats indices as a triangle list, indices past the last whole triangle
+//are dropped by flat_normals and ignored by the others. Winding is taken as in cube111,
+//(b - a) x (c - a) faces out.
+impl IndexedPositionColorNormal {
+    //Every triangle gets its own three vertices with the face's normal
+    pub fn flat_normals(&mut self) {
+        let mut verts = Vec::with_capacity(self.indices.len() / 3 * 3);
+        let mut uvs = vec![];
+        for triangle in self.indices.chunks_exact(3) {
+            let triangle = [triangle[0], triangle[1], triangle[2]].map(|index| index as usize);
+            let corners = triangle.map(|index| self.verts[index]);
+            let normal = face_normal(corners.map(|vert| vert.position));
+            verts.extend(corners.map(|vert| PositionColorNormal { normal, ..vert }));
+            if self.has_uvs() {
+                uvs.extend(triangle.map(|index| self.uvs[index]));
+            }
+        }
+        self.indices = (0..verts.len() as u32).collect();
+        self.verts = verts;
+        self.uvs = uvs;
+        self.refresh_tangents();
+    }
+
+    //Each vertex gets the normals of the faces around it, weighted by the angle of the face's
+    //corner so how a surface is cut into triangles doesn't change the result. Vertices are
+    //never split, so shared vertices on hard edges get rounded off.
+    pub fn smooth_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.verts.len()];
+        for corner in self.corners() {
+            normals[corner.vertex as usize] += corner.face_normal * corner.angle;
+        }
+        for (vert, normal) in self.verts.iter_mut().zip(normals) {
+            vert.normal = normal.normalize_or_zero();
+        }
+        self.refresh_tangents();
+    }
+
+    //Smooth normals, except where faces meet at more than hard_angle radians. Vertices on
+    //those edges are split so each side keeps its own normal, e.g. a cube's corners become
+    //three vertices.
+    pub fn split_normals(&mut self, hard_angle: f32) {
+        let cos_hard_angle = hard_angle.cos();
+        let corners = self.corners();
+        let mut vertex_corners: Vec<Vec<usize>> = vec![vec![]; self.verts.len()];
+        for (position, corner) in corners.iter().enumerate() {
+            vertex_corners[corner.vertex as usize].push(position);
+        }
+        //Normals each original vertex has been given and the vertex holding them, the first
+        //keeps the original vertex
+        let mut assigned: Vec<Vec<(Vec3, u32)>> = vec![vec![]; self.verts.len()];
+        for corner in &corners {
+            let normal = vertex_corners[corner.vertex as usize]
+                .iter()
+                .map(|&other| &corners[other])
+                .filter(|other| other.face_normal.dot(corner.face_normal) >= cos_hard_angle)
+                .map(|other| other.face_normal * other.angle)
+                .sum::<Vec3>()
+                .normalize_or_zero();
+            let assigned = &mut assigned[corner.vertex as usize];
+            let existing = assigned
+                .iter()
+                .find(|(assigned_normal, _)| assigned_normal.abs_diff_eq(normal, NORMAL_EPSILON))
+                .map(|(_, vertex)| *vertex);
+            let vertex = match existing {
+                Some(vertex) => vertex,
+                None => {
+                    let vertex = if assigned.is_empty() {
+                        corner.vertex
+                    } else {
+                        self.duplicate_vertex(corner.vertex)
+                    };
+                    self.verts[vertex as usize].normal = normal;
+                    assigned.push((normal, vertex));
+                    vertex
+                }
+            };
+            self.indices[corner.index] = vertex;
+        }
+        self.refresh_tangents();
+    }
+
+    fn corners(&self) -> Vec<Corner> {
+        let mut corners = Vec::with_capacity(self.indices.len());
+        for (triangle, indices) in self.indices.chunks_exact(3).enumerate() {
+            let positions = [0, 1, 2].map(|corner| self.verts[indices[corner] as usize].position);
+            let face_normal = face_normal(positions);
+            for corner in 0..3 {
+                let position = positions[corner];
+                //Degenerate triangles add nothing, angle_between would be NaN
+                let angle = if face_normal == Vec3::ZERO {
+                    0.0
+                } else {
+                    (positions[(corner + 1) % 3] - position)
+                        .angle_between(positions[(corner + 2) % 3] - position)
+                };
+                corners.push(Corner {
+                    index: triangle * 3 + corner,
+                    vertex: indices[corner],
+                    face_normal,
+                    angle,
+                });
+            }
+        }
+        corners
+    }
+}
+
+//Zero for degenerate triangles
+fn face_normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+    (b - a).cross(c - a).normalize_or_zero()
+}
+
+//Position, colour and normal streams, plus uv0 and tangent ones with uvs. Tangents are zero
+//if generate_tangents hasn't been called, which turns normal mapping off.
+impl From<IndexedPositionColorNormal> for Mesh {
+    fn from(mesh: IndexedPositionColorNormal) -> Self {
+        let stream = |value: fn(&PositionColorNormal) -> Vec3| {
+            VertexStream::Vec3(mesh.verts.iter().map(value).collect())
+        };
+        let mut streams = vec![
+            (VertexAttribute::Color, stream(|vert| vert.color)),
+            (VertexAttribute::Normal, stream(|vert| vert.normal)),
+        ];
+        if mesh.has_uvs() {
+            let tangent = |index: usize| mesh.tangents.get(index).copied().unwrap_or(Vec4::ZERO);
+            streams.push((VertexAttribute::Uv0, VertexStream::Vec2(mesh.uvs.clone())));
+            streams.push((
+                VertexAttribute::Tangent,
+                VertexStream::Vec4((0..mesh.verts.len()).map(tangent).collect()),
+            ));
+        }
+        let positions = mesh.verts.iter().map(|vert| vert.position).collect();
+        let mut converted = Mesh::new(positions, Indices::compact(mesh.indices));
+        for (attribute, stream) in streams {
+            //Every stream is built with one value per vert, so always matches
+            let _ = converted.set_attribute(attribute, stream);
+        }
+        converted
+    }
 }
 
 impl Component for MeshFilterComponent {}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_4, PI};
+
+    use super::*;
+
+    fn mesh(positions: &[Vec3], indices: Vec<u32>) -> IndexedPositionColorNormal {
+        let verts = positions
+            .iter()
+            .map(|position| PositionColorNormal {
+                position: *position,
+                color: Vec3::ONE,
+                normal: Vec3::ZERO,
+            })
+            .collect();
+        IndexedPositionColorNormal::new(verts, indices)
+    }
+
+    //Eight shared corners wound like cube111's
+    fn cube() -> IndexedPositionColorNormal {
+        let positions = [
+            Vec3::new(-0.5, 0.5, -0.5),
+            Vec3::new(0.5, 0.5, -0.5),
+            Vec3::new(0.5, -0.5, -0.5),
+            Vec3::new(-0.5, -0.5, -0.5),
+            Vec3::new(-0.5, 0.5, 0.5),
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(0.5, -0.5, 0.5),
+            Vec3::new(-0.5, -0.5, 0.5),
+        ];
+        let indices = vec![
+            0, 1, 2, 2, 3, 0, 5, 4, 7, 7, 6, 5, 3, 2, 6, 6, 7, 3, 1, 5, 6, 6, 2, 1, 4, 0, 3, 3, 7,
+            4, 4, 5, 1, 1, 0, 4,
+        ];
+        mesh(&positions, indices)
+    }
+
+    fn quad() -> IndexedPositionColorNormal {
+        let positions = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        mesh(&positions, vec![0, 1, 2, 2, 3, 0])
+    }
+
+    fn normals(mesh: &IndexedPositionColorNormal) -> Vec<Vec3> {
+        mesh.verts.iter().map(|vert| vert.normal).collect()
+    }
+
+    fn assert_normals_eq(left: &[Vec3], right: &[Vec3]) {
+        assert_eq!(left.len(), right.len());
+        for (left, right) in left.iter().zip(right) {
+            assert!(left.abs_diff_eq(*right, 1e-5), "{left} != {right}");
+        }
+    }
+
+    //Unit length along one axis, pointing away from the centre of the cube
+    fn is_cube_face_normal(vert: &PositionColorNormal) -> bool {
+        let axis_aligned = [Vec3::X, Vec3::Y, Vec3::Z]
+            .iter()
+            .any(|axis| (vert.normal.dot(*axis).abs() - 1.0).abs() < 1e-5);
+        axis_aligned && (vert.normal.dot(vert.position) - 0.5).abs() < 1e-5
+    }
+
+    #[test]
+    fn split_normals_gives_each_cube_face_its_own_vertices() {
+        let mut cube = cube();
+        cube.split_normals(FRAC_PI_4);
+        assert_eq!(cube.verts.len(), 24);
+        assert_eq!(cube.indices.len(), 36);
+        assert!(
+            cube.verts.iter().all(is_cube_face_normal),
+            "{:?}",
+            normals(&cube)
+        );
+    }
+
+    #[test]
+    fn split_normals_past_every_edge_match_smooth_normals() {
+        let mut split = cube();
+        split.split_normals(PI);
+        let mut smooth = cube();
+        smooth.smooth_normals();
+        assert_eq!(split.verts.len(), 8);
+        assert_normals_eq(&normals(&split), &normals(&smooth));
+    }
+
+    #[test]
+    fn smooth_normals_point_out_of_cube_corners() {
+        let mut cube = cube();
+        cube.smooth_normals();
+        assert_eq!(cube.verts.len(), 8);
+        for vert in &cube.verts {
+            assert!(
+                vert.normal.abs_diff_eq(vert.position.normalize(), 1e-5),
+                "{vert:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn smooth_normals_keep_split_normals() {
+        let mut quad = quad();
+        quad.split_normals(FRAC_PI_4);
+        let split = normals(&quad);
+        assert_normals_eq(&split, &[Vec3::Z; 4]);
+        quad.smooth_normals();
+        assert_normals_eq(&normals(&quad), &split);
+
+        //Split vertices only have the faces they were split for
+        let mut cube = cube();
+        cube.split_normals(FRAC_PI_4);
+        let split = normals(&cube);
+        cube.smooth_normals();
+        assert_normals_eq(&normals(&cube), &split);
+    }
+
+    #[test]
+    fn flat_normals_give_every_triangle_its_face_normal() {
+        let mut cube = cube();
+        cube.flat_normals();
+        assert_eq!(cube.verts.len(), 36);
+        assert_eq!(cube.indices, (0..36).collect::<Vec<u32>>());
+        assert!(
+            cube.verts.iter().all(is_cube_face_normal),
+            "{:?}",
+            normals(&cube)
+        );
+        for triangle in cube.verts.chunks_exact(3) {
+            assert!(triangle
+                .iter()
+                .all(|vert| vert.normal == triangle[0].normal));
+        }
+    }
+
+    #[test]
+    fn flat_normals_drop_trailing_indices() {
+        let mut quad = quad();
+        quad.indices.push(1);
+        quad.flat_normals();
+        assert_eq!(quad.verts.len(), 6);
+        assert_eq!(quad.indices.len(), 6);
+    }
+}
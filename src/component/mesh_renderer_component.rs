@@ -12,18 +12,51 @@
 //See the License for the specific language governing permissions and
 //limitations under the License.
 
+use glam::Vec4;
+
+use crate::resource::materials::MaterialHandle;
+
 use super::Component;
 
+//Parts of the frame, drawn in this order. Opaque objects are drawn front to back
+//grouped by pipeline, material and mesh, transparent ones back to front with their
+//pipeline's alpha blended variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum DrawPass {
+    #[default]
+    Opaque,
+    Transparent,
+}
+
 //shaders and the like here ?
 #[derive(Debug)]
 #[repr(C)]
 pub struct MeshRendererComponent {
-    pub pipeline_key: String,
+    //Into the scene's Materials, which also picks the pipeline
+    pub material: MaterialHandle,
+    pub pass: DrawPass,
+    //Multiplied into the vertex colours per instance, so entities sharing a material can
+    //still be coloured individually without a re-upload
+    pub tint: Vec4,
 }
 
 impl MeshRendererComponent {
-    pub fn new(pipeline_key: String) -> Self {
-        MeshRendererComponent { pipeline_key }
+    pub fn new(material: MaterialHandle) -> Self {
+        MeshRendererComponent {
+            material,
+            pass: DrawPass::Opaque,
+            tint: Vec4::ONE,
+        }
+    }
+
+    pub fn with_pass(mut self, pass: DrawPass) -> Self {
+        self.pass = pass;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Vec4) -> Self {
+        self.tint = tint;
+        self
     }
 }
 
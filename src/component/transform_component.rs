@@ -47,6 +47,10 @@ impl TransformComponent {
         if let Some(parent) = self.parent {}
     }
 
+    pub fn translation(&self) -> Vec3 {
+        self.transform.w_axis.xyz()
+    }
+
     //Seems a bit sketch
     pub fn forward(&self) -> Vec3 {
         self.transform.row(3).xyz()
@@ -0,0 +1,233 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalSize;
+use winit::window::{Fullscreen, WindowAttributes};
+
+use crate::display::{DisplaySettings, PresentModeSetting};
+use crate::profiler::parse_frame_range;
+use crate::replay::ReplayMode;
+
+pub const DEFAULT_CONFIG_PATH: &str = "engine.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    Maximized,
+    //Fullscreen window on the current monitor, no video mode change
+    Borderless,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub title: String,
+    //Physical pixels
+    pub width: u32,
+    pub height: u32,
+    pub mode: WindowMode,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: String::from("Balloon Engine"),
+            width: 1280,
+            height: 720,
+            mode: WindowMode::Windowed,
+        }
+    }
+}
+
+impl WindowConfig {
+    pub fn attributes(&self) -> WindowAttributes {
+        let attributes = WindowAttributes::default()
+            .with_title(self.title.clone())
+            .with_inner_size(PhysicalSize::new(self.width, self.height));
+        match self.mode {
+            WindowMode::Windowed => attributes,
+            WindowMode::Maximized => attributes.with_maximized(true),
+            WindowMode::Borderless => attributes.with_fullscreen(Some(Fullscreen::Borderless(None))),
+        }
+    }
+}
+
+//Loaded from engine.toml, then overridden by command line flags. Inserted into the scene
+//as a resource so controllers can read it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    pub window: WindowConfig,
+    //tracing target filter, e.g. "info" or "debug,vulkano=warn"
+    pub log_filter: String,
+    //Name of the scene to start in, registered with AppBuilder::add_scene
+    pub scene: String,
+    pub display: DisplaySettings,
+    //1 turns MSAA off, clamped to what the GPU supports
+    pub msaa_samples: u32,
+    pub clear_color: [f32; 4],
+    pub input_bindings: PathBuf,
+    //Run headless_frames frames without a window or GPU, then exit
+    pub headless: bool,
+    pub headless_frames: u64,
+    pub stats_csv: Option<PathBuf>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            window: WindowConfig::default(),
+            log_filter: String::from("debug"),
+            scene: String::from("scene_one"),
+            display: DisplaySettings::default(),
+            msaa_samples: 1,
+            clear_color: [1.0, 1.0, 1.0, 1.0],
+            input_bindings: PathBuf::from("assets/input.ron"),
+            headless: false,
+            headless_frames: 600,
+            stats_csv: None,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse config {}", path.display()))
+    }
+
+    //The file named by --config, or engine.toml when it exists, with the flags applied on top
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let mut config = match cli.config.as_ref() {
+            Some(path) => EngineConfig::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                EngineConfig::from_file(DEFAULT_CONFIG_PATH)?
+            }
+            None => EngineConfig::default(),
+        };
+        cli.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.window.width == 0 || self.window.height == 0 {
+            bail!("Window size must be non zero, got {}x{}", self.window.width, self.window.height);
+        }
+        if !self.msaa_samples.is_power_of_two() || self.msaa_samples > 64 {
+            bail!("msaa_samples must be 1, 2, 4, 8, 16, 32 or 64, got {}", self.msaa_samples);
+        }
+        Ok(())
+    }
+}
+
+//Flags override the matching engine.toml settings
+#[derive(Debug, Parser)]
+#[command(name = "balloon", about = "Balloon engine", version)]
+pub struct Cli {
+    /// Config file, defaults to engine.toml in the working directory if there is one
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub width: Option<u32>,
+    #[arg(long)]
+    pub height: Option<u32>,
+    #[arg(long, value_enum)]
+    pub window_mode: Option<WindowMode>,
+    /// tracing target filter, e.g. "info" or "debug,vulkano=warn"
+    #[arg(long, value_name = "FILTER")]
+    pub log: Option<String>,
+    #[arg(long)]
+    pub scene: Option<String>,
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentModeSetting>,
+    #[arg(long, value_name = "SAMPLES")]
+    pub msaa: Option<u32>,
+    /// 0 for unlimited
+    #[arg(long)]
+    pub fps: Option<f32>,
+    /// Simulate this many frames with no window or GPU, then exit
+    #[arg(long, value_name = "FRAMES")]
+    pub headless: Option<u64>,
+    /// Record input to a file for replaying later
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+    /// Write every frame's statistics to a CSV file
+    #[arg(long, value_name = "FILE")]
+    pub stats_csv: Option<PathBuf>,
+    /// Write a Chrome trace of --trace-frames to a file
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+    /// first..last, inclusive
+    #[arg(long, value_name = "RANGE", value_parser = parse_frame_range, requires = "trace")]
+    pub trace_frames: Option<Range<u64>>,
+}
+
+impl Cli {
+    pub fn apply(&self, config: &mut EngineConfig) {
+        if let Some(width) = self.width {
+            config.window.width = width;
+        }
+        if let Some(height) = self.height {
+            config.window.height = height;
+        }
+        if let Some(mode) = self.window_mode {
+            config.window.mode = mode;
+        }
+        if let Some(log) = self.log.as_ref() {
+            config.log_filter = log.clone();
+        }
+        if let Some(scene) = self.scene.as_ref() {
+            config.scene = scene.clone();
+        }
+        if let Some(present_mode) = self.present_mode {
+            config.display.present_mode = present_mode;
+        }
+        if let Some(msaa) = self.msaa {
+            config.msaa_samples = msaa;
+        }
+        if let Some(fps) = self.fps {
+            config.display.target_fps = Some(fps);
+        }
+        if let Some(frames) = self.headless {
+            config.headless = true;
+            config.headless_frames = frames;
+        }
+        if let Some(stats_csv) = self.stats_csv.as_ref() {
+            config.stats_csv = Some(stats_csv.clone());
+        }
+    }
+
+    pub fn replay_mode(&self) -> ReplayMode {
+        match (self.record.as_ref(), self.replay.as_ref()) {
+            (Some(path), _) => ReplayMode::Record(path.clone()),
+            (None, Some(path)) => ReplayMode::Replay(path.clone()),
+            (None, None) => ReplayMode::Off,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use vulkano::swapchain::PresentMode;
+use winit::event_loop::ControlFlow;
+
+//Sleeping is only accurate to a millisecond or so, the last stretch before a frame is spun out
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentModeSetting {
+    //Vsync, always supported
+    #[default]
+    Fifo,
+    //Vsync without blocking, the newest frame replaces any queued one
+    Mailbox,
+    //No vsync, may tear
+    Immediate,
+}
+
+impl PresentModeSetting {
+    //Falls back to Fifo, the only mode every driver has to support
+    pub fn choose(self, supported: impl IntoIterator<Item = PresentMode>) -> PresentMode {
+        let wanted = match self {
+            PresentModeSetting::Fifo => PresentMode::Fifo,
+            PresentModeSetting::Mailbox => PresentMode::Mailbox,
+            PresentModeSetting::Immediate => PresentMode::Immediate,
+        };
+        if supported.into_iter().any(|mode| mode == wanted) {
+            wanted
+        } else {
+            PresentMode::Fifo
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    //None or 0 runs as fast as the present mode allows
+    pub target_fps: Option<f32>,
+    pub present_mode: PresentModeSetting,
+    //Drop to background_fps while the window is unfocused or hidden, rendering stops while hidden
+    pub throttle_in_background: bool,
+    pub background_fps: f32,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            target_fps: Some(144.0),
+            present_mode: PresentModeSetting::Fifo,
+            throttle_in_background: true,
+            background_fps: 10.0,
+        }
+    }
+}
+
+//Paces frames to a target rate by sleeping through the event loop then spinning to the deadline
+#[derive(Debug)]
+pub struct FrameLimiter {
+    frame_time: Option<Duration>,
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new(target_fps: Option<f32>) -> Self {
+        let mut limiter = FrameLimiter {
+            frame_time: None,
+            next_frame: None,
+        };
+        limiter.set_target_fps(target_fps);
+        limiter
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: Option<f32>) {
+        let frame_time = target_fps
+            .filter(|fps| fps.is_finite() && *fps > 0.0)
+            .map(|fps| Duration::from_secs_f32(1.0 / fps));
+        if frame_time != self.frame_time {
+            self.frame_time = frame_time;
+            //Don't make a faster rate wait out the slow rate's deadline
+            self.next_frame = None;
+        }
+    }
+
+    pub fn target_fps(&self) -> Option<f32> {
+        self.frame_time.map(|frame_time| 1.0 / frame_time.as_secs_f32())
+    }
+
+    //True when a frame should run now. Returns false if woken early, e.g. by input.
+    pub fn wait_for_frame(&mut self) -> bool {
+        let Some(frame_time) = self.frame_time else {
+            return true;
+        };
+        let now = Instant::now();
+        let deadline = self.next_frame.unwrap_or(now);
+        if deadline > now + SPIN_MARGIN {
+            return false;
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+        //Step from the deadline so the average rate holds, but never bank frames after a stall
+        self.next_frame = Some((deadline + frame_time).max(Instant::now()));
+        true
+    }
+
+    pub fn control_flow(&self) -> ControlFlow {
+        match self.next_frame {
+            Some(deadline) if self.frame_time.is_some() => {
+                ControlFlow::WaitUntil(deadline.checked_sub(SPIN_MARGIN).unwrap_or(deadline))
+            }
+            _ => ControlFlow::Poll,
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Result};
+use glam::{Vec2, Vec3, Vec4};
+use itertools::Either;
+use vulkano::{
+    format::Format,
+    pipeline::graphics::vertex_input::{
+        VertexBufferDescription, VertexInputRate, VertexMemberInfo,
+    },
+};
+
+//A per vertex value a mesh can hold a stream of. Each is read by the vertex shader input
+//called name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum VertexAttribute {
+    Position,
+    Normal,
+    //xyz along increasing u, w the sign of the bitangent
+    Tangent,
+    Uv0,
+    Uv1,
+    Color,
+    //Up to four joints moving the vertex, and how much each one does
+    Joints,
+    Weights,
+}
+
+impl VertexAttribute {
+    pub fn name(self) -> &'static str {
+        match self {
+            VertexAttribute::Position => "position",
+            VertexAttribute::Normal => "normal",
+            VertexAttribute::Tangent => "tangent",
+            VertexAttribute::Uv0 => "uv0",
+            VertexAttribute::Uv1 => "uv1",
+            VertexAttribute::Color => "color",
+            VertexAttribute::Joints => "joints",
+            VertexAttribute::Weights => "weights",
+        }
+    }
+
+    pub fn format(self) -> Format {
+        match self {
+            VertexAttribute::Position | VertexAttribute::Normal | VertexAttribute::Color => {
+                Format::R32G32B32_SFLOAT
+            }
+            VertexAttribute::Tangent | VertexAttribute::Weights => Format::R32G32B32A32_SFLOAT,
+            VertexAttribute::Uv0 | VertexAttribute::Uv1 => Format::R32G32_SFLOAT,
+            VertexAttribute::Joints => Format::R16G16B16A16_UINT,
+        }
+    }
+
+    //Streams aren't interleaved, every attribute is a vertex buffer of its own
+    pub fn description(self) -> VertexBufferDescription {
+        let format = self.format();
+        VertexBufferDescription {
+            members: HashMap::from([(
+                self.name().to_string(),
+                VertexMemberInfo {
+                    offset: 0,
+                    format,
+                    num_elements: 1,
+                },
+            )]),
+            stride: format.block_size() as u32,
+            input_rate: VertexInputRate::Vertex,
+        }
+    }
+}
+
+//One attribute's value for every vertex
+#[derive(Debug, Clone, PartialEq)]
+pub enum VertexStream {
+    Vec2(Vec<Vec2>),
+    Vec3(Vec<Vec3>),
+    Vec4(Vec<Vec4>),
+    U16x4(Vec<[u16; 4]>),
+}
+
+impl VertexStream {
+    pub fn len(&self) -> usize {
+        match self {
+            VertexStream::Vec2(values) => values.len(),
+            VertexStream::Vec3(values) => values.len(),
+            VertexStream::Vec4(values) => values.len(),
+            VertexStream::U16x4(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //Has to match the format of the attribute it's stored under
+    pub fn format(&self) -> Format {
+        match self {
+            VertexStream::Vec2(_) => Format::R32G32_SFLOAT,
+            VertexStream::Vec3(_) => Format::R32G32B32_SFLOAT,
+            VertexStream::Vec4(_) => Format::R32G32B32A32_SFLOAT,
+            VertexStream::U16x4(_) => Format::R16G16B16A16_UINT,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            VertexStream::Vec2(values) => bytemuck::cast_slice(values),
+            VertexStream::Vec3(values) => bytemuck::cast_slice(values),
+            VertexStream::Vec4(values) => bytemuck::cast_slice(values),
+            VertexStream::U16x4(values) => bytemuck::cast_slice(values),
+        }
+    }
+
+    pub fn as_vec2(&self) -> Option<&[Vec2]> {
+        match self {
+            VertexStream::Vec2(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec3(&self) -> Option<&[Vec3]> {
+        match self {
+            VertexStream::Vec3(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec4(&self) -> Option<&[Vec4]> {
+        match self {
+            VertexStream::Vec4(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_u16x4(&self) -> Option<&[[u16; 4]]> {
+        match self {
+            VertexStream::U16x4(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec3_mut(&mut self) -> Option<&mut [Vec3]> {
+        match self {
+            VertexStream::Vec3(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+//u16 indices halve the index buffer of meshes with fewer than 65536 vertices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    //u16 if every index fits in one
+    pub fn compact(indices: Vec<u32>) -> Self {
+        if indices.iter().all(|index| *index <= u16::MAX as u32) {
+            Indices::U16(indices.into_iter().map(|index| index as u16).collect())
+        } else {
+            Indices::U32(indices)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        match self {
+            Indices::U16(indices) => Either::Left(indices.iter().map(|index| *index as u32)),
+            Indices::U32(indices) => Either::Right(indices.iter().copied()),
+        }
+    }
+}
+
+impl From<Vec<u16>> for Indices {
+    fn from(indices: Vec<u16>) -> Self {
+        Indices::U16(indices)
+    }
+}
+
+impl From<Vec<u32>> for Indices {
+    fn from(indices: Vec<u32>) -> Self {
+        Indices::U32(indices)
+    }
+}
+
+//Streams of vertex attributes, one value per vertex in each, and the indices drawing them.
+//Every mesh has positions, a pipeline can draw it if it has all of the pipeline's
+//attributes as well.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    streams: BTreeMap<VertexAttribute, VertexStream>,
+    pub indices: Indices,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vec3>, indices: impl Into<Indices>) -> Self {
+        Mesh {
+            streams: BTreeMap::from([(VertexAttribute::Position, VertexStream::Vec3(positions))]),
+            indices: indices.into(),
+        }
+    }
+
+    pub fn with_attribute(
+        mut self,
+        attribute: VertexAttribute,
+        stream: VertexStream,
+    ) -> Result<Self> {
+        self.set_attribute(attribute, stream)?;
+        Ok(self)
+    }
+
+    //The stream has to be in the attribute's format with a value for every vertex
+    pub fn set_attribute(
+        &mut self,
+        attribute: VertexAttribute,
+        stream: VertexStream,
+    ) -> Result<()> {
+        check_stream(attribute, &stream, self.vertex_count())?;
+        self.streams.insert(attribute, stream);
+        Ok(())
+    }
+
+    //Positions can't be removed
+    pub fn remove_attribute(&mut self, attribute: VertexAttribute) -> Option<VertexStream> {
+        if attribute == VertexAttribute::Position {
+            return None;
+        }
+        self.streams.remove(&attribute)
+    }
+
+    pub fn attribute(&self, attribute: VertexAttribute) -> Option<&VertexStream> {
+        self.streams.get(&attribute)
+    }
+
+    //Replacing the stream with one of another length or format fails validate
+    pub fn attribute_mut(&mut self, attribute: VertexAttribute) -> Option<&mut VertexStream> {
+        self.streams.get_mut(&attribute)
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = (VertexAttribute, &VertexStream)> + '_ {
+        self.streams
+            .iter()
+            .map(|(attribute, stream)| (*attribute, stream))
+    }
+
+    pub fn has_attribute(&self, attribute: VertexAttribute) -> bool {
+        self.streams.contains_key(&attribute)
+    }
+
+    //The ones out of attributes this mesh has no stream for
+    pub fn missing_attributes(&self, attributes: &[VertexAttribute]) -> Vec<VertexAttribute> {
+        attributes
+            .iter()
+            .copied()
+            .filter(|attribute| !self.has_attribute(*attribute))
+            .collect()
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        self.attribute(VertexAttribute::Position)
+            .and_then(VertexStream::as_vec3)
+            .unwrap_or_default()
+    }
+
+    pub fn positions_mut(&mut self) -> &mut [Vec3] {
+        self.attribute_mut(VertexAttribute::Position)
+            .and_then(VertexStream::as_vec3_mut)
+            .unwrap_or_default()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions().len()
+    }
+
+    //Checked before upload, every stream in its attribute's format and as long as the
+    //positions, and no index past the last vertex
+    pub fn validate(&self) -> Result<()> {
+        let vertex_count = self.vertex_count();
+        for (attribute, stream) in self.attributes() {
+            check_stream(attribute, stream, vertex_count)?;
+        }
+        if let Some(index) = self
+            .indices
+            .iter()
+            .find(|index| *index as usize >= vertex_count)
+        {
+            bail!("Index {index} is out of range of {vertex_count} vertices");
+        }
+        Ok(())
+    }
+}
+
+fn check_stream(
+    attribute: VertexAttribute,
+    stream: &VertexStream,
+    vertex_count: usize,
+) -> Result<()> {
+    if stream.format() != attribute.format() {
+        bail!(
+            "{attribute:?} stream is {:?}, expected {:?}",
+            stream.format(),
+            attribute.format()
+        );
+    }
+    //Positions set the vertex count, so a new position stream can be any length
+    if attribute != VertexAttribute::Position && stream.len() != vertex_count {
+        bail!(
+            "{attribute:?} stream has {} values for {vertex_count} vertices",
+            stream.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Mesh {
+        Mesh::new(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            Indices::compact(vec![0, 1, 2]),
+        )
+        .with_attribute(
+            VertexAttribute::Normal,
+            VertexStream::Vec3(vec![Vec3::Z; 3]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn compact_picks_the_smallest_index_type() {
+        let small = Indices::compact(vec![0, 1, u16::MAX as u32]);
+        assert_eq!(small, Indices::U16(vec![0, 1, u16::MAX]));
+        let large = Indices::compact(vec![0, 1, u16::MAX as u32 + 1]);
+        assert_eq!(large, Indices::U32(vec![0, 1, u16::MAX as u32 + 1]));
+        assert_eq!(large.iter().collect::<Vec<_>>(), vec![0, 1, 65536]);
+        assert_eq!(small.len(), 3);
+        assert!(Indices::compact(vec![]).is_empty());
+    }
+
+    #[test]
+    fn validate_accepts_a_consistent_mesh() {
+        assert!(triangle().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_indices() {
+        let mut mesh = triangle();
+        mesh.indices = Indices::compact(vec![0, 1, 3]);
+        assert!(mesh.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_streams_edited_out_of_shape() {
+        let mut mesh = triangle();
+        *mesh.attribute_mut(VertexAttribute::Normal).unwrap() =
+            VertexStream::Vec3(vec![Vec3::Z; 2]);
+        assert!(mesh.validate().is_err());
+
+        let mut mesh = triangle();
+        *mesh.attribute_mut(VertexAttribute::Normal).unwrap() =
+            VertexStream::Vec4(vec![Vec4::Z; 3]);
+        assert!(mesh.validate().is_err());
+
+        //Fewer positions leave the other streams too long
+        let mut mesh = triangle();
+        mesh.set_attribute(
+            VertexAttribute::Position,
+            VertexStream::Vec3(vec![Vec3::ZERO; 2]),
+        )
+        .unwrap();
+        assert!(mesh.validate().is_err());
+    }
+
+    #[test]
+    fn set_attribute_rejects_mismatched_streams() {
+        let mut mesh = triangle();
+        assert!(mesh
+            .set_attribute(
+                VertexAttribute::Uv0,
+                VertexStream::Vec2(vec![Vec2::ZERO; 4])
+            )
+            .is_err());
+        assert!(mesh
+            .set_attribute(
+                VertexAttribute::Uv0,
+                VertexStream::Vec3(vec![Vec3::ZERO; 3])
+            )
+            .is_err());
+        assert!(!mesh.has_attribute(VertexAttribute::Uv0));
+        assert_eq!(mesh.remove_attribute(VertexAttribute::Position), None);
+        assert_eq!(
+            mesh.missing_attributes(&[
+                VertexAttribute::Position,
+                VertexAttribute::Normal,
+                VertexAttribute::Uv0
+            ]),
+            vec![VertexAttribute::Uv0]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use glam::{Vec3};
+use glam::{Mat3, Mat4, Vec3, Vec4};
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex as VulkanVertex};
 
 #[derive(BufferContents, VulkanVertex, Clone, Debug, Copy)]
@@ -12,3 +12,34 @@ pub struct PositionColorNormal {
     pub normal: Vec3,
 }
 
+
+//Per instance attributes, every draw reads its model matrices from a buffer of these
+#[derive(BufferContents, VulkanVertex, Clone, Debug, Copy)]
+#[repr(C)]
+pub struct InstanceData {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_model: [[f32; 4]; 4],
+    //Multiplies the vertex colours
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_tint: [f32; 4],
+    //Inverse transpose of the model matrix's rotation and scale, for transforming normals
+    #[format(R32G32B32_SFLOAT)]
+    pub instance_normal: [[f32; 3]; 3],
+}
+
+impl InstanceData {
+    pub fn new(model: Mat4, tint: Vec4) -> Self {
+        let linear = Mat3::from_mat4(model);
+        //A matrix that flattens an axis has no inverse, its normals are meaningless anyway
+        let normal = if linear.determinant().abs() > f32::EPSILON {
+            linear.inverse().transpose()
+        } else {
+            linear
+        };
+        InstanceData {
+            instance_model: model.to_cols_array_2d(),
+            instance_tint: tint.to_array(),
+            instance_normal: normal.to_cols_array_2d(),
+        }
+    }
+}
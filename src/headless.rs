@@ -0,0 +1,143 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    replay::{InputReplay, DEFAULT_FIXED_DELTA},
+    resource::input_state::{InputEvent, InputMap, InputState},
+    scene::{lock::RecoverLock, Scene},
+    system::{simulation_system::SimulationSystem, System},
+};
+
+//Ticks a scene's non render systems on a fixed timestep with no window or GPU,
+//for tests and servers. Input comes from a replay or from pushing events in directly.
+pub struct HeadlessRunner {
+    scene: Arc<RwLock<Scene>>,
+    simulation_system: SimulationSystem,
+    fixed_delta: Duration,
+    frame: u64,
+    input_state: Arc<RwLock<InputState>>,
+    replay: Option<InputReplay>,
+}
+
+impl HeadlessRunner {
+    pub fn new(scene: Arc<RwLock<Scene>>) -> Self {
+        let existing = scene.read_or_recover().get_resource::<InputState>();
+        let input_state = existing.unwrap_or_else(|| {
+            scene
+                .write_or_recover()
+                .insert_resource(InputState::new(InputMap::with_defaults()))
+        });
+        HeadlessRunner {
+            scene,
+            simulation_system: SimulationSystem::new(),
+            fixed_delta: Duration::from_secs_f32(DEFAULT_FIXED_DELTA),
+            frame: 0,
+            input_state,
+            replay: None,
+        }
+    }
+
+    //Replaces the default systems, e.g. with one that has game systems added
+    pub fn with_simulation_system(mut self, simulation_system: SimulationSystem) -> Self {
+        self.simulation_system = simulation_system;
+        self
+    }
+
+    pub fn add_system(&mut self, system: impl System + 'static) {
+        self.simulation_system.add_system(Box::new(system));
+    }
+
+    pub fn with_fixed_delta(mut self, fixed_delta: Duration) -> Self {
+        self.fixed_delta = fixed_delta;
+        self
+    }
+
+    //Uses the recording's timestep, the scene should have been built from its seed
+    pub fn with_replay(mut self, replay: InputReplay) -> Self {
+        self.fixed_delta = replay.header().delta();
+        self.replay = Some(replay);
+        self
+    }
+
+    pub fn scene(&self) -> Arc<RwLock<Scene>> {
+        Arc::clone(&self.scene)
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn is_disabled(&self, index: usize) -> bool {
+        self.simulation_system.controller_system.is_disabled(index)
+    }
+
+    pub fn disabled_controllers(&self) -> Vec<usize> {
+        self.simulation_system.controller_system.disabled_controllers()
+    }
+
+    //Applied before the next step, same as input arriving from the window
+    pub fn send_input(&mut self, event: InputEvent) {
+        self.input_state.write_or_recover().apply(&event);
+    }
+
+    pub fn step(&mut self) {
+        if let Some(replay) = self.replay.as_mut() {
+            let events = replay.events_for(self.frame);
+            let mut input_state = self.input_state.write_or_recover();
+            for event in events.iter() {
+                input_state.apply(event);
+            }
+        }
+        self.simulation_system.step(&self.scene, self.fixed_delta);
+        self.frame += 1;
+    }
+
+    pub fn run_frames(&mut self, frames: u64) {
+        for _ in 0..frames {
+            self.step();
+        }
+    }
+
+    //Runs every remaining event in the replay, returns straight away without one
+    pub fn run_replay(&mut self) {
+        while self
+            .replay
+            .as_ref()
+            .is_some_and(|replay| !replay.is_finished())
+        {
+            self.step();
+        }
+    }
+
+    //Steps until the condition holds after a frame, returns the number of frames taken
+    pub fn run_until(
+        &mut self,
+        max_frames: u64,
+        mut condition: impl FnMut(&Scene) -> bool,
+    ) -> Result<u64> {
+        for frames in 1..=max_frames {
+            self.step();
+            if condition(&self.scene.read_or_recover()) {
+                return Ok(frames);
+            }
+        }
+        bail!("Condition not met within {max_frames} frames")
+    }
+}
@@ -0,0 +1,39 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+#![feature(iterator_try_collect)]
+#![feature(slice_iter_mut_as_mut_slice)]
+pub mod app;
+pub mod component;
+pub mod config;
+pub mod display;
+pub mod geometry;
+pub mod headless;
+pub mod plugin;
+pub mod prefabs;
+pub mod profiler;
+pub mod replay;
+pub mod resource;
+pub mod scene;
+pub mod shaders;
+pub mod stats;
+pub mod system;
+
+pub use app::builder::AppBuilder;
+pub use component::controller::Controller;
+pub use plugin::Plugin;
+pub use scene::Scene;
+pub use system::renderer_system::pipelines::RenderPipeline;
+pub use system::System;
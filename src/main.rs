@@ -12,32 +12,44 @@
 //See the License for the specific language governing permissions and
 //limitations under the License.
 
-#![feature(iterator_try_collect)]
-#![feature(slice_iter_mut_as_mut_slice)]
-mod app;
-mod component;
-mod geometry;
-mod prefabs;
-mod scene;
-mod shaders;
-mod system;
 
-//mod vulkan_device;
-//mod vulkan_instance;
-use app::{App, UserEvent};
-use tracing::{info, Level};
-use winit::event_loop::EventLoop;
+use balloon::config::{Cli, EngineConfig};
+use balloon::plugin::DefaultPlugins;
+use balloon::profiler;
+use balloon::scene::{scene_one::SceneOne, Scene, SceneCreate};
+use balloon::AppBuilder;
+use clap::Parser;
+use tracing::{level_filters::LevelFilter, warn};
+use tracing_subscriber::{filter::Targets, layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+//Skips startup so shader and pipeline creation don't swamp the capture
+const DEFAULT_TRACE_FRAMES: std::ops::Range<u64> = 60..180;
 
 fn main() {
-    tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG)
+    let cli = Cli::parse();
+    let config = EngineConfig::load(&cli).unwrap();
+
+    let (log_filter, filter_error) = match config.log_filter.parse::<Targets>() {
+        Ok(filter) => (filter, None),
+        Err(err) => (Targets::new().with_default(LevelFilter::DEBUG), Some(err)),
+    };
+    let (trace_layer, mut profiler) = profiler::chrome_trace_layer();
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(log_filter))
+        .with(trace_layer)
         .init();
-    let event_loop = EventLoop::<UserEvent>::with_user_event().build().unwrap();
-    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
-    //event_loop.set_control_flow(control_flow);
+    if let Some(err) = filter_error {
+        warn!("Bad log filter {:?} ({err}), logging at debug", config.log_filter);
+    }
 
-    //#[allow(deprecated)]
-    let mut state = App::new(&event_loop).unwrap();
+    if let Some(path) = cli.trace.as_ref() {
+        let frames = cli.trace_frames.clone().unwrap_or(DEFAULT_TRACE_FRAMES);
+        profiler.capture(path, frames);
+    }
 
-    let _ = event_loop.run_app(&mut state);
+    let mut app = AppBuilder::new(config);
+    app.set_replay_mode(cli.replay_mode())
+        .add_plugin(DefaultPlugins::new(Some(profiler)))
+        .add_scene("scene_one", |seed| Ok(<Scene as SceneCreate<SceneOne>>::new(seed)));
+    app.run().unwrap();
 }
@@ -0,0 +1,114 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::warn;
+
+use crate::{
+    app::builder::AppBuilder, profiler::Profiler, resource::input_state::InputMap,
+    stats::FrameStats,
+};
+
+//A feature a game can opt in to, e.g. rendering or debug tools. Plugins add their
+//systems and resources to the builder and can read or adjust its config.
+pub trait Plugin {
+    fn build(self, app: &mut AppBuilder) -> Result<()>;
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+//Window, swapchain and renderer. Without it the app runs headless.
+pub struct RenderPlugin;
+
+impl Plugin for RenderPlugin {
+    fn build(self, app: &mut AppBuilder) -> Result<()> {
+        if app.config().headless {
+            return Ok(());
+        }
+        app.enable_rendering();
+        Ok(())
+    }
+}
+
+//InputState resource fed from window events, bindings from config.input_bindings
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(self, app: &mut AppBuilder) -> Result<()> {
+        let map = load_input_map(&app.config().input_bindings);
+        app.enable_input(map);
+        Ok(())
+    }
+}
+
+fn load_input_map(path: &Path) -> InputMap {
+    if !path.exists() {
+        return InputMap::with_defaults();
+    }
+    InputMap::from_file(path).unwrap_or_else(|err| {
+        warn!("{err:#}, falling back to the default bindings");
+        InputMap::with_defaults()
+    })
+}
+
+//Frame statistics and Chrome trace captures. The profiler comes from
+//profiler::chrome_trace_layer, whose layer has to be added to the tracing subscriber.
+pub struct DebugToolsPlugin {
+    profiler: Option<Profiler>,
+}
+
+impl DebugToolsPlugin {
+    pub fn new(profiler: Option<Profiler>) -> Self {
+        DebugToolsPlugin { profiler }
+    }
+}
+
+impl Plugin for DebugToolsPlugin {
+    fn build(self, app: &mut AppBuilder) -> Result<()> {
+        let mut frame_stats = FrameStats::default();
+        if let Some(path) = app.config().stats_csv.as_ref() {
+            frame_stats = frame_stats.with_csv(path)?;
+        }
+        app.set_frame_stats(frame_stats);
+        if let Some(profiler) = self.profiler {
+            app.set_profiler(profiler);
+        }
+        Ok(())
+    }
+}
+
+//Rendering, input and debug tools
+pub struct DefaultPlugins {
+    profiler: Option<Profiler>,
+}
+
+impl DefaultPlugins {
+    pub fn new(profiler: Option<Profiler>) -> Self {
+        DefaultPlugins { profiler }
+    }
+}
+
+impl Plugin for DefaultPlugins {
+    fn build(self, app: &mut AppBuilder) -> Result<()> {
+        app.add_plugin(RenderPlugin)
+            .add_plugin(InputPlugin)
+            .add_plugin(DebugToolsPlugin::new(self.profiler));
+        Ok(())
+    }
+}
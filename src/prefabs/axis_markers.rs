@@ -7,7 +7,8 @@ use crate::{
         transform_component::TransformComponent,
     },
     geometry::vertex::PositionColorNormal,
-    scene::Scene,
+    resource::materials::{Material, Materials},
+    scene::{lock::RecoverLock, Scene},
 };
 use anyhow::Result;
 //use nalgebra_glm::Vec3;
@@ -43,26 +44,25 @@ pub fn make_axis_markers(scene: Arc<RwLock<Scene>>, magnitude: f32) -> Result<us
         .map(|((x, y, z), (r, g, b))| PositionColorNormal {
             position: Vec3::new(*x, *y, *z),
             color: Vec3::new(*r, *g, *b),
-            normal: Vec3::new(0.0, 0.0, 0.0),
+            //A line list has no faces to generate normals from, zero normals are drawn unlit
+            normal: Vec3::ZERO,
         })
         .collect();
 
-    let verts = IndexedPositionColorNormal {
-        verts: verts,
-        indices: indices,
-    };
+    let verts = IndexedPositionColorNormal::new(verts, indices);
 
     info!("Verts axis markers: {:?}", verts);
-    let mut scene_mutable_lock = scene.write().unwrap();
+    let mut scene_mutable_lock = scene.write_or_recover();
+    let material = scene_mutable_lock
+        .get_or_insert_resource::<Materials>()
+        .write_or_recover()
+        .get_or_add("axis_markers", || Material::new("lines"));
     let ent = scene_mutable_lock.new_entity();
     scene_mutable_lock.add_component_to_entity(
         ent,
-        MeshFilterComponent {
-            indexed_verts: verts,
-        },
+        MeshFilterComponent::new(verts),
     );
-    scene_mutable_lock
-        .add_component_to_entity(ent, MeshRendererComponent::new(String::from("lines")));
+    scene_mutable_lock.add_component_to_entity(ent, MeshRendererComponent::new(material));
     scene_mutable_lock.add_component_to_entity(ent, TransformComponent::new());
     drop(scene_mutable_lock);
     Ok(ent)
@@ -11,6 +11,7 @@
 //WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 //See the License for the specific language governing permissions and
 //limitations under the License.
+use std::f32::consts::FRAC_PI_4;
 use std::sync::{Arc, RwLock};
 
 use crate::{
@@ -23,7 +24,8 @@ use crate::{
         transform_component::TransformComponent,
     },
     geometry::vertex::PositionColorNormal,
-    scene::Scene,
+    resource::materials::{Material, Materials},
+    scene::{lock::RecoverLock, Scene},
 };
 use anyhow::Result;
 use glam::Vec3;
@@ -48,6 +50,51 @@ pub fn make_111_cube(scene: Arc<RwLock<Scene>>, cube_type: CubeType) -> Result<u
     //let rub: (f32, f32, f32) = (0.5, -0.5,0.5);
     //let lub: (f32, f32, f32)= (-0.5,-0.5,0.5);
 
+    let mut scene_mutable_lock = scene.write_or_recover();
+    let mesh_filter = scene_mutable_lock
+        .get_or_insert_resource::<CubeMesh>()
+        .read_or_recover()
+        .0
+        .clone();
+
+    let material = scene_mutable_lock
+        .get_or_insert_resource::<Materials>()
+        .write_or_recover()
+        .get_or_add("cube", || Material::new("lit"));
+
+    let ent = scene_mutable_lock.new_entity();
+    scene_mutable_lock.add_component_to_entity(ent, mesh_filter);
+    scene_mutable_lock.add_component_to_entity(ent, MeshRendererComponent::new(material));
+    scene_mutable_lock.add_component_to_entity(ent, TransformComponent::new());
+    let controller: Box<dyn Controller>;
+    match cube_type {
+        CubeType::MORPHER => {
+            controller = Box::new(ColorController::new());
+        }
+        CubeType::ROTATOR => {
+            controller = Box::new(RotatorController::new());
+        }
+    }
+
+    //let controller = ColorController::new();
+
+    //let controller: Box<dyn Controller> = controller;
+
+    scene_mutable_lock.add_component_to_entity(ent, Arc::new(RwLock::new(controller)));
+    drop(scene_mutable_lock);
+    Ok(ent)
+}
+
+//Every cube starts out sharing this mesh, so cubes that never edit it are instanced together
+struct CubeMesh(MeshFilterComponent);
+
+impl Default for CubeMesh {
+    fn default() -> Self {
+        CubeMesh(MeshFilterComponent::new(cube_mesh()))
+    }
+}
+
+fn cube_mesh() -> IndexedPositionColorNormal {
     //Front side
     let ldf: (f32, f32, f32) = (-0.5, 0.5, -0.5);
     let rdf: (f32, f32, f32) = (0.5, 0.5, -0.5);
@@ -75,38 +122,8 @@ pub fn make_111_cube(scene: Arc<RwLock<Scene>>, cube_type: CubeType) -> Result<u
         })
         .collect();
 
-    let verts = IndexedPositionColorNormal {
-        verts: verts,
-        indices: indices,
-    };
-
-    let mut scene_mutable_lock = scene.write().unwrap();
-
-    let ent = scene_mutable_lock.new_entity();
-    scene_mutable_lock.add_component_to_entity(
-        ent,
-        MeshFilterComponent {
-            indexed_verts: verts,
-        },
-    );
-    scene_mutable_lock
-        .add_component_to_entity(ent, MeshRendererComponent::new(String::from("teapot")));
-    scene_mutable_lock.add_component_to_entity(ent, TransformComponent::new());
-    let controller: Box<dyn Controller>;
-    match cube_type {
-        CubeType::MORPHER => {
-            controller = Box::new(ColorController::new());
-        }
-        CubeType::ROTATOR => {
-            controller = Box::new(RotatorController::new());
-        }
-    }
-
-    //let controller = ColorController::new();
-
-    //let controller: Box<dyn Controller> = controller;
-
-    scene_mutable_lock.add_component_to_entity(ent, Arc::new(RwLock::new(controller)));
-    drop(scene_mutable_lock);
-    Ok(ent)
+    let mut mesh = IndexedPositionColorNormal::new(verts, indices);
+    //Every edge of a cube is hard, each face gets its own four vertices
+    mesh.split_normals(FRAC_PI_4);
+    mesh
 }
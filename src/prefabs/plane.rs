@@ -1,9 +1,57 @@
 use std::sync::{Arc, RwLock};
 
-use crate::scene::Scene;
+use crate::{
+    component::{
+        mesh_filter_component::{IndexedPositionColorNormal, MeshFilterComponent},
+        mesh_renderer_component::MeshRendererComponent,
+        transform_component::TransformComponent,
+    },
+    geometry::vertex::PositionColorNormal,
+    resource::materials::{Material, Materials},
+    scene::{lock::RecoverLock, Scene},
+};
 
 use anyhow::Result;
+use glam::{Vec2, Vec3};
 
+//World units covered by one repeat of the plane's texture
+const TILE_SIZE: f32 = 10.0;
+
+//A textured square on the XZ plane, magnitude wide and facing +Y
 pub fn make_plane(scene: Arc<RwLock<Scene>>, magnitude: f32) -> Result<usize> {
-    Ok(1)
+    let half = magnitude / 2.0;
+    let corners = [
+        Vec2::new(-half, -half),
+        Vec2::new(-half, half),
+        Vec2::new(half, half),
+        Vec2::new(half, -half),
+    ];
+    let verts = corners
+        .iter()
+        .map(|corner| PositionColorNormal {
+            position: Vec3::new(corner.x, 0.0, corner.y),
+            color: Vec3::ONE,
+            normal: Vec3::ZERO,
+        })
+        .collect();
+    let uvs = corners.iter().map(|corner| *corner / TILE_SIZE).collect();
+    let mut mesh = IndexedPositionColorNormal::new(verts, vec![0, 1, 2, 2, 3, 0]).with_uvs(uvs);
+    mesh.smooth_normals();
+    mesh.generate_tangents();
+
+    let mut scene_mutable_lock = scene.write_or_recover();
+    let material = scene_mutable_lock
+        .get_or_insert_resource::<Materials>()
+        .write_or_recover()
+        .get_or_add("plane", || {
+            Material::new("textured")
+                .with_albedo_texture("assets/textures/checker.png")
+                .with_roughness(0.9)
+        });
+    let ent = scene_mutable_lock.new_entity();
+    scene_mutable_lock.add_component_to_entity(ent, MeshFilterComponent::new(mesh));
+    scene_mutable_lock.add_component_to_entity(ent, MeshRendererComponent::new(material));
+    scene_mutable_lock.add_component_to_entity(ent, TransformComponent::new());
+    drop(scene_mutable_lock);
+    Ok(ent)
 }
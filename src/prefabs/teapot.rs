@@ -8,11 +8,16 @@ use crate::{
         transform_component::TransformComponent,
     },
     geometry::vertex::PositionColorNormal,
-    scene::Scene,
+    resource::{
+        materials::{Material, Materials},
+        random::Random,
+    },
+    scene::{lock::RecoverLock, Scene},
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use glam::{Mat4, Vec3};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::{
     f32::consts::PI,
     sync::{Arc, RwLock},
@@ -31,9 +36,14 @@ pub fn make_teapot(
     translation: Vec3,
     cube_type: CubeType,
 ) -> Result<usize> {
+    //Split off the scene's seeded stream so recorded sessions rebuild the same colours
+    let random = scene
+        .read_or_recover()
+        .get_resource::<Random>()
+        .context("Scene has no Random resource to colour the teapot from")?;
+    let mut rng = StdRng::from_rng(random.write_or_recover().rng())?;
     let mut verts: Vec<PositionColorNormal> = vec![];
     for (teapot_pos, teapot_normal) in POSITIONS.iter().zip(NORMALS.iter()) {
-        let mut rng = rand::thread_rng();
         verts.push(PositionColorNormal {
             position: Vec3 {
                 x: teapot_pos.position[0],
@@ -57,22 +67,21 @@ pub fn make_teapot(
 
     swap_chunks_mut(new_indices.iter_mut().as_mut_slice());
 
-    let verts = IndexedPositionColorNormal {
-        verts: verts,
-        indices: new_indices.to_vec(),
-    };
+    let verts = IndexedPositionColorNormal::new(verts, new_indices.to_vec());
+
+    let mut scene_mutable_lock = scene.write_or_recover();
 
-    let mut scene_mutable_lock = scene.write().unwrap();
+    let material = scene_mutable_lock
+        .get_or_insert_resource::<Materials>()
+        .write_or_recover()
+        .get_or_add("teapot", || Material::new("lit").with_roughness(0.3));
 
     let ent = scene_mutable_lock.new_entity();
     scene_mutable_lock.add_component_to_entity(
         ent,
-        MeshFilterComponent {
-            indexed_verts: verts,
-        },
+        MeshFilterComponent::new(verts),
     );
-    scene_mutable_lock
-        .add_component_to_entity(ent, MeshRendererComponent::new(String::from("teapot")));
+    scene_mutable_lock.add_component_to_entity(ent, MeshRendererComponent::new(material));
     let mut transform_component = TransformComponent::new();
     transform_component.transform_op(|transform| {
         Mat4::from_translation(translation) * Mat4::from_rotation_x(PI) * transform
@@ -0,0 +1,284 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+//Chrome trace event export for the engine's tracing spans. Open the output in
+//https://ui.perfetto.dev or chrome://tracing. Spans are only buffered while a capture runs.
+
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{info, warn, Subscriber};
+use tracing_subscriber::layer::Context as LayerContext;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    //Small stable ids per thread, ThreadId can't be turned into a number on stable
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+struct TraceEvent {
+    name: &'static str,
+    target: &'static str,
+    phase: &'static str,
+    micros: f64,
+    thread: u64,
+    args: Option<Map<String, Value>>,
+}
+
+struct Shared {
+    recording: AtomicBool,
+    epoch: Instant,
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+//Span fields, kept in the span's extensions and attached as args on its begin event
+struct SpanArgs(Map<String, Value>);
+
+impl Visit for SpanArgs {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{value:?}")));
+    }
+}
+
+pub struct ChromeTraceLayer {
+    shared: Arc<Shared>,
+}
+
+impl ChromeTraceLayer {
+    fn push<S>(&self, id: &Id, phase: &'static str, with_args: bool, ctx: &LayerContext<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        if !self.shared.recording.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let args = with_args
+            .then(|| span.extensions().get::<SpanArgs>().map(|args| args.0.clone()))
+            .flatten();
+        let event = TraceEvent {
+            name: span.metadata().name(),
+            target: span.metadata().target(),
+            phase,
+            micros: self.shared.epoch.elapsed().as_secs_f64() * 1_000_000.0,
+            thread: THREAD_ID.with(|id| *id),
+            args,
+        };
+        self.shared
+            .events
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(event);
+    }
+}
+
+impl<S> Layer<S> for ChromeTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        //Outside a capture this runs for every span in the engine, so nothing is allocated.
+        //Spans opened before a capture starts go without args.
+        if attrs.fields().is_empty() || !self.shared.recording.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut args = SpanArgs(Map::new());
+        attrs.record(&mut args);
+        span.extensions_mut().insert(args);
+    }
+
+    fn on_enter(&self, id: &Id, ctx: LayerContext<'_, S>) {
+        self.push(id, "B", true, &ctx);
+    }
+
+    fn on_exit(&self, id: &Id, ctx: LayerContext<'_, S>) {
+        self.push(id, "E", false, &ctx);
+    }
+}
+
+struct Capture {
+    path: PathBuf,
+    frames: Range<u64>,
+}
+
+//App side of the layer, starts and stops captures on frame boundaries and writes the file
+pub struct Profiler {
+    shared: Arc<Shared>,
+    capture: Option<Capture>,
+}
+
+//Add the layer to the subscriber and hand the profiler to the app
+pub fn chrome_trace_layer() -> (ChromeTraceLayer, Profiler) {
+    let shared = Arc::new(Shared {
+        recording: AtomicBool::new(false),
+        epoch: Instant::now(),
+        events: Mutex::new(vec![]),
+    });
+    (
+        ChromeTraceLayer {
+            shared: Arc::clone(&shared),
+        },
+        Profiler {
+            shared,
+            capture: None,
+        },
+    )
+}
+
+impl Profiler {
+    //Records frames in the range, replacing any capture that hasn't finished
+    pub fn capture(&mut self, path: impl Into<PathBuf>, frames: Range<u64>) {
+        if self.is_capturing() {
+            warn!("Abandoning the trace capture in progress");
+            self.set_recording(false);
+            self.take_events();
+        }
+        self.capture = Some(Capture {
+            path: path.into(),
+            frames,
+        });
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    pub fn begin_frame(&mut self, frame: u64) {
+        if let Some(capture) = self.capture.as_ref() {
+            if capture.frames.contains(&frame) && !self.shared.recording.load(Ordering::Relaxed) {
+                info!("Trace capture started at frame {frame}");
+                self.set_recording(true);
+            }
+        }
+    }
+
+    pub fn end_frame(&mut self, frame: u64) {
+        let finished = self
+            .capture
+            .as_ref()
+            .is_some_and(|capture| frame + 1 >= capture.frames.end);
+        if finished {
+            self.finish();
+        }
+    }
+
+    //Writes whatever has been captured so far, e.g. when exiting mid capture
+    pub fn finish(&mut self) {
+        let Some(capture) = self.capture.take() else {
+            return;
+        };
+        self.set_recording(false);
+        let events = self.take_events();
+        match write_trace(&capture.path, &events) {
+            Ok(()) => info!(
+                "Wrote {} trace events for frames {:?} to {}",
+                events.len(),
+                capture.frames,
+                capture.path.display()
+            ),
+            Err(err) => warn!("{err:#}"),
+        }
+    }
+
+    fn set_recording(&self, recording: bool) {
+        self.shared.recording.store(recording, Ordering::Relaxed);
+    }
+
+    fn take_events(&self) -> Vec<TraceEvent> {
+        std::mem::take(
+            &mut *self
+                .shared
+                .events
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+    }
+}
+
+//first..last, both inclusive, e.g. "100..220"
+pub fn parse_frame_range(text: &str) -> Result<Range<u64>> {
+    let (first, last) = text
+        .split_once("..")
+        .ok_or_else(|| anyhow!("Expected a frame range like 100..220, got {text}"))?;
+    let first: u64 = first.trim().parse()?;
+    let last: u64 = last.trim().parse()?;
+    Ok(first..last.max(first) + 1)
+}
+
+fn write_trace(path: &Path, events: &[TraceEvent]) -> Result<()> {
+    let pid = std::process::id();
+    let trace_events: Vec<Value> = events
+        .iter()
+        .map(|event| {
+            let mut value = json!({
+                "name": event.name,
+                "cat": event.target,
+                "ph": event.phase,
+                "ts": event.micros,
+                "pid": pid,
+                "tid": event.thread,
+            });
+            if let Some(args) = event.args.as_ref() {
+                value["args"] = Value::Object(args.clone());
+            }
+            value
+        })
+        .collect();
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create trace file {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(
+        &mut writer,
+        &json!({ "traceEvents": trace_events, "displayTimeUnit": "ms" }),
+    )?;
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write trace file {}", path.display()))
+}
@@ -0,0 +1,199 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+//Input recordings are line delimited RON, a header line then one (frame, event) per line:
+//(seed: 42, fixed_delta: 0.016666668)
+//(0, WindowResized((800.0, 600.0)))
+//(12, Pressed(Key(KeyW)))
+//Frame n's events are the ones the app received before running its nth game loop.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::resource::input_state::InputEvent;
+
+pub const DEFAULT_FIXED_DELTA: f32 = 1.0 / 60.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayMode {
+    Off,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub seed: u64,
+    //Seconds per frame, recorded sessions don't use wall clock time
+    pub fixed_delta: f32,
+}
+
+impl ReplayHeader {
+    pub fn new(seed: u64) -> Self {
+        ReplayHeader {
+            seed,
+            fixed_delta: DEFAULT_FIXED_DELTA,
+        }
+    }
+
+    pub fn delta(&self) -> Duration {
+        Duration::from_secs_f32(self.fixed_delta)
+    }
+}
+
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: impl AsRef<Path>, header: &ReplayHeader) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+        let mut recorder = InputRecorder {
+            writer: BufWriter::new(file),
+        };
+        recorder.write_line(header)?;
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, frame: u64, event: &InputEvent) -> Result<()> {
+        self.write_line(&(frame, event))
+    }
+
+    //Called once a frame so a crash loses at most the frame in progress
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().context("Failed to flush recording")
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        let line = ron::to_string(value)?;
+        writeln!(self.writer, "{line}").context("Failed to write recording")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputReplay {
+    header: ReplayHeader,
+    events: VecDeque<(u64, InputEvent)>,
+}
+
+impl InputReplay {
+    //Events must be sorted by frame, which is how the recorder writes them
+    pub fn new(header: ReplayHeader, events: Vec<(u64, InputEvent)>) -> Self {
+        InputReplay {
+            header,
+            events: events.into(),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read recording {}", path.display()))?;
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines
+            .next()
+            .ok_or_else(|| anyhow!("Recording {} is empty", path.display()))?;
+        let header: ReplayHeader = ron::from_str(header)
+            .with_context(|| format!("Bad header in recording {}", path.display()))?;
+        let events = lines
+            .map(|(number, line)| {
+                ron::from_str(line).with_context(|| {
+                    format!("Bad event on line {} of {}", number + 1, path.display())
+                })
+            })
+            .collect::<Result<Vec<(u64, InputEvent)>>>()?;
+        Ok(InputReplay::new(header, events))
+    }
+
+    pub fn header(&self) -> &ReplayHeader {
+        &self.header
+    }
+
+    //Removes and returns everything due on or before the given frame
+    pub fn events_for(&mut self, frame: u64) -> Vec<InputEvent> {
+        let mut due = vec![];
+        while let Some((_, event)) = self
+            .events
+            .front()
+            .filter(|(event_frame, _)| *event_frame <= frame)
+        {
+            due.push(*event);
+            self.events.pop_front();
+        }
+        due
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+//Seed and input source for one run, resolved from the replay mode before the scene is built
+pub struct ReplaySession {
+    pub seed: u64,
+    pub recorder: Option<InputRecorder>,
+    pub replay: Option<InputReplay>,
+}
+
+impl ReplaySession {
+    pub fn start(mode: ReplayMode) -> Result<Self> {
+        match mode {
+            ReplayMode::Off => Ok(ReplaySession {
+                seed: rand::random(),
+                recorder: None,
+                replay: None,
+            }),
+            ReplayMode::Record(path) => {
+                let header = ReplayHeader::new(rand::random());
+                info!("Recording input to {}, seed {}", path.display(), header.seed);
+                Ok(ReplaySession {
+                    seed: header.seed,
+                    recorder: Some(InputRecorder::create(&path, &header)?),
+                    replay: None,
+                })
+            }
+            ReplayMode::Replay(path) => {
+                let replay = InputReplay::from_file(&path)?;
+                info!("Replaying input from {}, seed {}", path.display(), replay.header().seed);
+                Ok(ReplaySession {
+                    seed: replay.header().seed,
+                    recorder: None,
+                    replay: Some(replay),
+                })
+            }
+        }
+    }
+
+    //Recorded and replayed runs step by a fixed delta instead of wall clock time
+    pub fn fixed_delta(&self) -> Option<Duration> {
+        match (self.recorder.as_ref(), self.replay.as_ref()) {
+            (_, Some(replay)) => Some(replay.header().delta()),
+            (Some(_), None) => Some(ReplayHeader::new(self.seed).delta()),
+            (None, None) => None,
+        }
+    }
+}
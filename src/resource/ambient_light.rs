@@ -0,0 +1,39 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use glam::Vec3;
+
+//Light that reaches every surface equally, so faces turned away from every light aren't black
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientLight {
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl AmbientLight {
+    pub fn new(color: Vec3, intensity: f32) -> Self {
+        AmbientLight { color, intensity }
+    }
+
+    pub fn radiance(&self) -> Vec3 {
+        self.color * self.intensity
+    }
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        AmbientLight::new(Vec3::ONE, 0.15)
+    }
+}
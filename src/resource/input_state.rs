@@ -0,0 +1,363 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+//Touchpads report scroll in pixels, roughly this many make up one wheel line
+const PIXELS_PER_LINE: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    MouseButton(MouseButton),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisSource {
+    //+1 while positive is held, -1 while negative is held
+    Buttons { positive: Binding, negative: Binding },
+    //Pixels moved since the last frame
+    MouseMotionX,
+    MouseMotionY,
+    //Lines scrolled since the last frame, pixel scrolling is converted to lines
+    Scroll,
+    ScrollX,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub source: AxisSource,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+//Named actions and axes to physical inputs, loaded from a RON file like
+//(actions: {"jump": [Key(Space)], "fire": [MouseButton(Left)]},
+// axes: {"look_x": [(source: MouseMotionX, scale: 1.0)]})
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputMap {
+    #[serde(default)]
+    pub actions: HashMap<String, Vec<Binding>>,
+    #[serde(default)]
+    pub axes: HashMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+    //The bindings the engine's own controllers expect
+    pub fn with_defaults() -> Self {
+        let mut map = InputMap::default();
+        map.bind_action("move_forward", Binding::Key(KeyCode::KeyW));
+        map.bind_action("move_back", Binding::Key(KeyCode::KeyS));
+        map.bind_action("move_left", Binding::Key(KeyCode::KeyA));
+        map.bind_action("move_right", Binding::Key(KeyCode::KeyD));
+        map.bind_action("move_down", Binding::Key(KeyCode::KeyQ));
+        map.bind_action("move_up", Binding::Key(KeyCode::KeyE));
+        map.bind_action("jump", Binding::Key(KeyCode::Space));
+        map.bind_axis("look_x", AxisSource::MouseMotionX, 1.0);
+        map.bind_axis("look_y", AxisSource::MouseMotionY, 1.0);
+        map.bind_axis("zoom", AxisSource::Scroll, 1.0);
+        map.bind_action("toggle_cursor_grab", Binding::Key(KeyCode::Tab));
+        map.bind_action("capture_trace", Binding::Key(KeyCode::F12));
+        map
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read input bindings {}", path.display()))?;
+        ron::from_str(&text)
+            .with_context(|| format!("Could not parse input bindings {}", path.display()))
+    }
+
+    pub fn bind_action(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, source: AxisSource, scale: f32) {
+        self.axes
+            .entry(axis.to_string())
+            .or_default()
+            .push(AxisBinding { source, scale });
+    }
+
+    //Replaces every binding for the action, e.g. from a settings menu
+    pub fn rebind_action(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.actions.insert(action.to_string(), bindings);
+    }
+
+    pub fn rebind_axis(&mut self, axis: &str, bindings: Vec<AxisBinding>) {
+        self.axes.insert(axis.to_string(), bindings);
+    }
+}
+
+//Everything the app feeds into InputState, kept as data so sessions can be recorded and replayed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Pressed(Binding),
+    Released(Binding),
+    MouseMotion(Vec2),
+    Scroll(Vec2),
+    ScrollPixels(Vec2),
+    CursorMoved(Option<Vec2>),
+    WindowResized(Vec2),
+    FocusLost,
+}
+
+//Raw device state fed in by the app plus the per frame action and axis values controllers read
+#[derive(Debug, Default)]
+pub struct InputState {
+    map: InputMap,
+    held: HashSet<Binding>,
+    //Pressed at some point since the last frame, so taps shorter than a frame still register
+    tapped: HashSet<Binding>,
+    released: HashSet<Binding>,
+    mouse_delta: Vec2,
+    scroll_lines: Vec2,
+    scroll_pixels: Vec2,
+    //Physical pixels from the window's top left, None while the cursor is outside the window
+    cursor_position: Option<Vec2>,
+    window_size: Vec2,
+    cursor_grabbed: bool,
+    actions: HashSet<String>,
+    previous_actions: HashSet<String>,
+    axes: HashMap<String, f32>,
+}
+
+impl InputState {
+    pub fn new(map: InputMap) -> Self {
+        InputState {
+            map,
+            ..Default::default()
+        }
+    }
+
+    pub fn map(&self) -> &InputMap {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut InputMap {
+        &mut self.map
+    }
+
+    pub fn set_map(&mut self, map: InputMap) {
+        self.map = map;
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.actions.contains(action)
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.actions.contains(action) && !self.previous_actions.contains(action)
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        !self.actions.contains(action) && self.previous_actions.contains(action)
+    }
+
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.axes.get(axis).copied().unwrap_or(0.0)
+    }
+
+    //+1 while only positive is pressed, -1 while only negative is pressed
+    pub fn action_axis(&self, positive: &str, negative: &str) -> f32 {
+        self.pressed(positive) as i32 as f32 - self.pressed(negative) as i32 as f32
+    }
+
+    pub fn key_held(&self, key: KeyCode) -> bool {
+        self.held.contains(&Binding::Key(key))
+    }
+
+    pub fn mouse_button_held(&self, button: MouseButton) -> bool {
+        self.held.contains(&Binding::MouseButton(button))
+    }
+
+    pub fn mouse_button_just_pressed(&self, button: MouseButton) -> bool {
+        self.tapped.contains(&Binding::MouseButton(button))
+    }
+
+    pub fn mouse_button_just_released(&self, button: MouseButton) -> bool {
+        self.released.contains(&Binding::MouseButton(button))
+    }
+
+    //Raw device motion since the last frame, unaffected by cursor acceleration or window edges
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    //Wheel lines since the last frame with pixel scrolling converted to lines, y is up
+    pub fn scroll_delta(&self) -> Vec2 {
+        self.scroll_lines + self.scroll_pixels / PIXELS_PER_LINE
+    }
+
+    pub fn scroll_pixel_delta(&self) -> Vec2 {
+        self.scroll_pixels
+    }
+
+    pub fn cursor_position(&self) -> Option<Vec2> {
+        self.cursor_position
+    }
+
+    //0..1 across the window with the origin at the top left
+    pub fn cursor_position_normalized(&self) -> Option<Vec2> {
+        if self.window_size.x <= 0.0 || self.window_size.y <= 0.0 {
+            return None;
+        }
+        self.cursor_position
+            .map(|position| position / self.window_size)
+    }
+
+    //-1..1 with y up, handy for picking rays
+    pub fn cursor_position_ndc(&self) -> Option<Vec2> {
+        self.cursor_position_normalized()
+            .map(|position| Vec2::new(position.x * 2.0 - 1.0, 1.0 - position.y * 2.0))
+    }
+
+    pub fn window_size(&self) -> Vec2 {
+        self.window_size
+    }
+
+    pub fn cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+
+    //Requests the cursor be locked and hidden for mouse look, the app applies it after the controllers run
+    pub fn set_cursor_grabbed(&mut self, grabbed: bool) {
+        self.cursor_grabbed = grabbed;
+    }
+
+    pub fn toggle_cursor_grab(&mut self) {
+        self.cursor_grabbed = !self.cursor_grabbed;
+    }
+
+    pub fn press(&mut self, binding: Binding) {
+        self.held.insert(binding);
+        self.tapped.insert(binding);
+    }
+
+    pub fn release(&mut self, binding: Binding) {
+        if self.held.remove(&binding) {
+            self.released.insert(binding);
+        }
+    }
+
+    //Drops everything held, e.g. when the window loses focus and won't see the key ups
+    pub fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    pub fn add_mouse_motion(&mut self, delta: Vec2) {
+        self.mouse_delta += delta;
+    }
+
+    pub fn add_scroll(&mut self, lines: Vec2) {
+        self.scroll_lines += lines;
+    }
+
+    pub fn add_scroll_pixels(&mut self, pixels: Vec2) {
+        self.scroll_pixels += pixels;
+    }
+
+    pub fn set_cursor_position(&mut self, position: Option<Vec2>) {
+        self.cursor_position = position;
+    }
+
+    pub fn set_window_size(&mut self, size: Vec2) {
+        self.window_size = size;
+    }
+
+    pub fn apply(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::Pressed(binding) => self.press(binding),
+            InputEvent::Released(binding) => self.release(binding),
+            InputEvent::MouseMotion(delta) => self.add_mouse_motion(delta),
+            InputEvent::Scroll(lines) => self.add_scroll(lines),
+            InputEvent::ScrollPixels(pixels) => self.add_scroll_pixels(pixels),
+            InputEvent::CursorMoved(position) => self.set_cursor_position(position),
+            InputEvent::WindowResized(size) => self.set_window_size(size),
+            InputEvent::FocusLost => {
+                self.release_all();
+                self.set_cursor_grabbed(false);
+            }
+        }
+    }
+
+    //Resolves actions and axes from everything received since the last frame, call before the controllers run
+    pub fn begin_frame(&mut self) {
+        self.previous_actions = std::mem::take(&mut self.actions);
+        let scroll = self.scroll_delta();
+        self.actions = self
+            .map
+            .actions
+            .iter()
+            .filter(|(_, bindings)| {
+                bindings
+                    .iter()
+                    .any(|binding| self.held.contains(binding) || self.tapped.contains(binding))
+            })
+            .map(|(action, _)| action.clone())
+            .collect();
+
+        self.axes = self
+            .map
+            .axes
+            .iter()
+            .map(|(axis, bindings)| {
+                let value = bindings
+                    .iter()
+                    .map(|binding| {
+                        let raw = match binding.source {
+                            AxisSource::Buttons { positive, negative } => {
+                                let positive = self.held.contains(&positive) as i32 as f32;
+                                let negative = self.held.contains(&negative) as i32 as f32;
+                                positive - negative
+                            }
+                            AxisSource::MouseMotionX => self.mouse_delta.x,
+                            AxisSource::MouseMotionY => self.mouse_delta.y,
+                            AxisSource::Scroll => scroll.y,
+                            AxisSource::ScrollX => scroll.x,
+                        };
+                        raw * binding.scale
+                    })
+                    .sum();
+                (axis.clone(), value)
+            })
+            .collect();
+    }
+
+    //Clears the per frame deltas, call after the controllers run
+    pub fn end_frame(&mut self) {
+        self.tapped.clear();
+        self.released.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_lines = Vec2::ZERO;
+        self.scroll_pixels = Vec2::ZERO;
+    }
+}
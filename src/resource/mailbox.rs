@@ -0,0 +1,86 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::mem;
+
+//A typed payload sent to another entity's controller, e.g. a door opened or damage taken event
+pub struct Message {
+    pub sender: Option<usize>,
+    payload: Box<dyn Any + Send + Sync>,
+}
+
+impl Message {
+    pub fn new<M: 'static + Send + Sync>(sender: Option<usize>, payload: M) -> Self {
+        Message {
+            sender,
+            payload: Box::new(payload),
+        }
+    }
+
+    pub fn is<M: 'static>(&self) -> bool {
+        self.payload.is::<M>()
+    }
+
+    pub fn get<M: 'static>(&self) -> Option<&M> {
+        self.payload.downcast_ref::<M>()
+    }
+}
+
+impl Debug for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Message")
+            .field("sender", &self.sender)
+            .finish_non_exhaustive()
+    }
+}
+
+//Messages sent during a frame are held here and handed out by the controller system at the
+//start of the next frame, so delivery order doesn't depend on controller scheduling
+#[derive(Debug, Default)]
+pub struct Mailbox {
+    pending: HashMap<usize, Vec<Message>>,
+}
+
+impl Mailbox {
+    pub fn new() -> Self {
+        Mailbox::default()
+    }
+
+    pub fn send<M: 'static + Send + Sync>(&mut self, target: usize, message: M) {
+        self.pending
+            .entry(target)
+            .or_default()
+            .push(Message::new(None, message));
+    }
+
+    pub fn send_from<M: 'static + Send + Sync>(&mut self, sender: usize, target: usize, message: M) {
+        self.pending
+            .entry(target)
+            .or_default()
+            .push(Message::new(Some(sender), message));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    //Takes everything sent so far, keyed by target entity
+    pub fn take(&mut self) -> HashMap<usize, Vec<Message>> {
+        mem::take(&mut self.pending)
+    }
+}
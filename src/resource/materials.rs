@@ -0,0 +1,222 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use glam::{Vec3, Vec4};
+
+//Names a material in the scene's Materials. The default handle is the default material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct MaterialHandle(u32);
+
+//How a mesh looks. Shared by every entity whose MeshRendererComponent holds its handle, the
+//renderer uploads its parameters once per frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    //The pipeline the renderer draws with, registered under this key
+    pub pipeline_key: String,
+    //Multiplies the vertex colours, alpha is used for blending in the transparent pass
+    pub base_color: Vec4,
+    //0 is mirror-like, 1 is fully diffuse
+    pub roughness: f32,
+    //0 is dielectric, 1 is metal tinted by base_color
+    pub metallic: f32,
+    //Added after lighting
+    pub emissive: Vec3,
+    //PNG, JPEG or KTX2 files, sampled by the textured pipeline. Albedo is read as sRGB and
+    //multiplies base_color, normal maps are tangent space.
+    pub albedo_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+    pub sampler: SamplerSettings,
+}
+
+impl Material {
+    pub fn new(pipeline_key: &str) -> Self {
+        Material {
+            pipeline_key: pipeline_key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_base_color(mut self, base_color: Vec4) -> Self {
+        self.base_color = base_color;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: Vec3) -> Self {
+        self.emissive = emissive;
+        self
+    }
+
+    pub fn with_albedo_texture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.albedo_texture = Some(path.into());
+        self
+    }
+
+    pub fn with_normal_texture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.normal_texture = Some(path.into());
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerSettings) -> Self {
+        self.sampler = sampler;
+        self
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            pipeline_key: String::from("lit"),
+            base_color: Vec4::ONE,
+            roughness: 0.5,
+            metallic: 0.0,
+            emissive: Vec3::ZERO,
+            albedo_texture: None,
+            normal_texture: None,
+            sampler: SamplerSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TextureFilter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TextureWrap {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+//How a material's textures are filtered and wrapped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerSettings {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    //Read distant and glancing surfaces from the smaller mip levels, without it only the
+    //full size level is used
+    pub mipmaps: bool,
+    //Max anisotropic samples, clamped to what the device supports. Ignored by devices
+    //without anisotropic filtering.
+    pub anisotropy: Option<f32>,
+}
+
+impl SamplerSettings {
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: Option<f32>) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        SamplerSettings {
+            filter: TextureFilter::Linear,
+            wrap: TextureWrap::Repeat,
+            mipmaps: true,
+            anisotropy: Some(8.0),
+        }
+    }
+}
+
+//Every material in a scene. Materials are never removed so handles stay valid.
+#[derive(Debug)]
+pub struct Materials {
+    materials: Vec<Material>,
+    names: HashMap<String, MaterialHandle>,
+}
+
+impl Materials {
+    pub fn new() -> Self {
+        Materials {
+            materials: vec![Material::default()],
+            names: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, material: Material) -> MaterialHandle {
+        self.materials.push(material);
+        MaterialHandle(self.materials.len() as u32 - 1)
+    }
+
+    //Prefabs share one material between every entity they make this way
+    pub fn get_or_add(&mut self, name: &str, create: impl FnOnce() -> Material) -> MaterialHandle {
+        if let Some(handle) = self.names.get(name) {
+            return *handle;
+        }
+        let handle = self.add(create());
+        self.names.insert(name.to_string(), handle);
+        handle
+    }
+
+    pub fn find(&self, name: &str) -> Option<MaterialHandle> {
+        self.names.get(name).copied()
+    }
+
+    pub fn get(&self, handle: MaterialHandle) -> Option<&Material> {
+        self.materials.get(handle.0 as usize)
+    }
+
+    //Changes show on every entity sharing the handle from the next frame
+    pub fn get_mut(&mut self, handle: MaterialHandle) -> Option<&mut Material> {
+        self.materials.get_mut(handle.0 as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+}
+
+impl Default for Materials {
+    fn default() -> Self {
+        Materials::new()
+    }
+}
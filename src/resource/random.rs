@@ -0,0 +1,58 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+//Seeded randomness so a recorded session replays identically. Controllers run in parallel,
+//so they should use entity_rng rather than sharing one stream whose order depends on scheduling.
+#[derive(Debug, Clone)]
+pub struct Random {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    //Single shared stream, only for code that runs in a fixed order like scene setup
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    //Same entity and frame always give the same stream for a given seed
+    pub fn entity_rng(&self, entity: usize, frame: u64) -> StdRng {
+        let mixed = splitmix64(
+            self.seed ^ splitmix64(entity as u64) ^ splitmix64(frame.wrapping_add(0x9E37_79B9)),
+        );
+        StdRng::seed_from_u64(mixed)
+    }
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,61 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::time::Duration;
+
+use crate::scene::{lock::RecoverLock, Scene};
+
+//Frame timing, advanced once per frame before the controllers run
+#[derive(Debug, Clone, Default)]
+pub struct Time {
+    delta: Duration,
+    elapsed: Duration,
+    frame: u64,
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Time::default()
+    }
+
+    pub fn advance(&mut self, delta: Duration) {
+        self.delta = delta;
+        self.elapsed += delta;
+        self.frame += 1;
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    //Shorthand for controllers, zero if the scene has no Time resource
+    pub fn delta_secs_in(scene: &Scene) -> f32 {
+        scene
+            .get_resource::<Time>()
+            .map_or(0.0, |time| time.read_or_recover().delta_secs())
+    }
+}
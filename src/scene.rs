@@ -18,20 +18,27 @@ use std::sync::Arc;
 use std::sync::RwLock;
 use crate::component::component_vec::ComponentVec;
 use crate::component::Component;
+use crate::resource::mailbox::Mailbox;
 use std::fmt::Debug;
+pub mod lock;
 pub mod scene_one;
-use std::{any::TypeId, collections::HashMap};
+use lock::RecoverLock;
+use std::{any::{Any, TypeId}, collections::HashMap};
+use tracing::warn;
 
 const THRESHOLD: usize = 50000;
 
 pub trait SceneCreate<T> {
-    fn new() -> Arc<RwLock<Scene>>;
+    //All randomness while building the scene should come from the seed, see Random
+    fn new(seed: u64) -> Arc<RwLock<Scene>>;
 }
 
 #[derive(Debug)]
 pub struct Scene {
     pub entities_index: usize,
     pub component_map: HashMap<TypeId, Arc<RwLock<dyn ComponentVec + Send + Sync>>>,
+    //Scene wide singletons, each value is an Arc<RwLock<R>> keyed by TypeId of R
+    pub resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
 }
 
 
@@ -105,7 +112,7 @@ impl Scene {
 
         for (key, value) in self.component_map.iter_mut() {
             //let mut value = value.write().unwrap()
-            value.write().unwrap().push_none();
+            value.write_or_recover().push_none();
         }
         self.entities_index += 1;
         entity_id
@@ -158,8 +165,7 @@ impl Scene {
     {
         if let Some(component_vec) = self.component_map.get(&TypeId::of::<ComponentType>()) {
             if let Some(component_vec) = component_vec
-                .write()
-                .unwrap()
+                .write_or_recover()
                 .as_any_mut()
                 .downcast_mut::<Vec<Option<ComponentType>>>()
             {
@@ -185,4 +191,40 @@ impl Scene {
             Arc::new(RwLock::new(new_component_vec)),
         );
     }
+
+    pub fn insert_resource<R: 'static + Send + Sync>(&mut self, resource: R) -> Arc<RwLock<R>> {
+        let resource = Arc::new(RwLock::new(resource));
+        self.resources
+            .insert(TypeId::of::<R>(), Box::new(Arc::clone(&resource)));
+        resource
+    }
+
+    pub fn get_resource<R: 'static + Send + Sync>(&self) -> Option<Arc<RwLock<R>>> {
+        self.resources
+            .get(&TypeId::of::<R>())?
+            .downcast_ref::<Arc<RwLock<R>>>()
+            .cloned()
+    }
+
+    pub fn get_or_insert_resource<R: 'static + Send + Sync + Default>(&mut self) -> Arc<RwLock<R>> {
+        match self.get_resource::<R>() {
+            Some(resource) => resource,
+            None => self.insert_resource(R::default()),
+        }
+    }
+
+    //Queues a message for the target entity's controller, delivered to on_message next frame
+    pub fn send<M: 'static + Send + Sync>(&self, target: usize, message: M) {
+        match self.get_resource::<Mailbox>() {
+            Some(mailbox) => mailbox.write_or_recover().send(target, message),
+            None => warn!(entity = target, "Scene has no mailbox, dropping message"),
+        }
+    }
+
+    pub fn send_from<M: 'static + Send + Sync>(&self, sender: usize, target: usize, message: M) {
+        match self.get_resource::<Mailbox>() {
+            Some(mailbox) => mailbox.write_or_recover().send_from(sender, target, message),
+            None => warn!(entity = target, "Scene has no mailbox, dropping message"),
+        }
+    }
 }
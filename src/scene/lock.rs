@@ -0,0 +1,42 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use tracing::warn;
+
+//A controller panicking while holding a component lock poisons it. The data behind the lock
+//is still usable, so recover it instead of letting every later unwrap take the app down.
+pub trait RecoverLock<T: ?Sized> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T>;
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+impl<T: ?Sized> RecoverLock<T> for RwLock<T> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(|poisoned| {
+            warn!("Recovering poisoned lock on {}", std::any::type_name::<T>());
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(|poisoned| {
+            warn!("Recovering poisoned lock on {}", std::any::type_name::<T>());
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
@@ -22,24 +22,37 @@ use rand::Rng;
 use tracing::info;
 
 use crate::{
-    component::{camera_component::CameraComponent, transform_component::TransformComponent},
-    prefabs::{axis_markers::make_axis_markers, cube111::make_111_cube, teapot::make_teapot},
+    component::{
+        camera_component::CameraComponent,
+        controller::{fly_camera_controller::FlyCameraController, Controller},
+        light_component::{DirectionalLight, PointLight},
+        transform_component::TransformComponent,
+    },
+    prefabs::{
+        axis_markers::make_axis_markers, cube111::make_111_cube, plane::make_plane,
+        teapot::make_teapot,
+    },
+    resource::{ambient_light::AmbientLight, mailbox::Mailbox, random::Random, time::Time},
 };
 
-use super::{Scene, SceneCreate};
+use super::{lock::RecoverLock, Scene, SceneCreate};
 #[derive(Debug)]
 pub struct SceneOne;
 
 //impl SceneType for SceneOne {}
 
 impl SceneCreate<SceneOne> for Scene {
-    fn new() -> Arc<RwLock<Scene>> {
+    fn new(seed: u64) -> Arc<RwLock<Scene>> {
         let scene = Self {
             entities_index: 0,
             component_map: HashMap::new(),
+            resources: HashMap::new(),
         };
         let scene = Arc::new(RwLock::new(scene));
-        let mut scene_mutable_lock = scene.write().unwrap();
+        let mut scene_mutable_lock = scene.write_or_recover();
+        scene_mutable_lock.insert_resource(Mailbox::new());
+        scene_mutable_lock.insert_resource(Time::new());
+        let random = scene_mutable_lock.insert_resource(Random::new(seed));
 
         let cam = scene_mutable_lock.new_entity();
         let mut cam_transform = TransformComponent::new();
@@ -55,13 +68,31 @@ impl SceneCreate<SceneOne> for Scene {
         );
         scene_mutable_lock.add_component_to_entity(cam, cam_transform);
         scene_mutable_lock.add_component_to_entity(cam, CameraComponent::new());
+        let cam_controller: Box<dyn Controller> = Box::new(FlyCameraController::new());
+        scene_mutable_lock.add_component_to_entity(cam, Arc::new(RwLock::new(cam_controller)));
+
+        scene_mutable_lock.insert_resource(AmbientLight::default());
+        let sun = scene_mutable_lock.new_entity();
+        scene_mutable_lock.add_component_to_entity(sun, TransformComponent::new());
+        scene_mutable_lock.add_component_to_entity(
+            sun,
+            DirectionalLight::new(Vec3::new(0.3, -1.0, 0.5), Vec3::ONE, 0.8),
+        );
+        //Between the teapots
+        let lamp = scene_mutable_lock.new_entity();
+        let mut lamp_transform = TransformComponent::new();
+        lamp_transform.set_transform(Mat4::from_translation(Vec3::new(250.0, 150.0, 250.0)));
+        scene_mutable_lock.add_component_to_entity(lamp, lamp_transform);
+        scene_mutable_lock.add_component_to_entity(
+            lamp,
+            PointLight::new(Vec3::new(1.0, 0.85, 0.6), 1.5, 600.0),
+        );
 
         //    Vertex{position:Vector3::new(0.5f64,-0.25f64,0f64), color:Vector3::new(0f64, 0f64, 1f64)},
         //    Vertex{position:Vector3::new(0f64,0.5f64,0f64), color:Vector3::new(0f64, 0f64, 1f64)},
         //    Vertex{position:Vector3::new(0.25f64,-0.1f64,-0.2f64), color:Vector3::new(0f64, 0f64, 1f64)}
         //]));
         drop(scene_mutable_lock);
-        let mut rng = rand::thread_rng();
         let cube1 =
             make_111_cube(scene.clone(), crate::prefabs::cube111::CubeType::MORPHER).unwrap();
         let cube2 =
@@ -100,14 +131,16 @@ impl SceneCreate<SceneOne> for Scene {
             let cube_index =
                 make_111_cube(scene.clone(), crate::prefabs::cube111::CubeType::ROTATOR).unwrap();
             info!("3");
-            let scene_lock = scene.read().unwrap();
+            let scene_lock = scene.read_or_recover();
             let transforms = scene_lock
                 .get_component_vec::<TransformComponent>()
                 .unwrap();
             info!("4");
-            let mut transforms = transforms.write().unwrap();
+            let mut transforms = transforms.write_or_recover();
             info!("5");
             if let Some(cube_transform) = transforms[cube_index].as_mut() {
+                let mut random = random.write_or_recover();
+                let rng = random.rng();
                 let (x, y, z) = (
                     rng.gen_range(-50.0..50.0),
                     rng.gen_range(-50.0..50.0),
@@ -120,13 +153,14 @@ impl SceneCreate<SceneOne> for Scene {
         }
         //let cube3 = make_111_cube(scene.clone(), crate::prefabs::cube111::CubeType::ROTATOR).unwrap();
         let _ = make_axis_markers(scene.clone(), 100.0);
+        let plane = make_plane(scene.clone(), 200.0).unwrap();
 
         //info!("Scene: {:#?}", scene_mutable_lock);
-        let scene_lock = scene.read().unwrap();
+        let scene_lock = scene.read_or_recover();
         let transforms = scene_lock
             .get_component_vec::<TransformComponent>()
             .unwrap();
-        let mut transforms = transforms.write().unwrap();
+        let mut transforms = transforms.write_or_recover();
         if let Some(cube_transform) = transforms[cube1].as_mut() {
             cube_transform.set_transform(
                 Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0)) * cube_transform.transform,
@@ -137,6 +171,10 @@ impl SceneCreate<SceneOne> for Scene {
                 Mat4::from_translation(Vec3::new(0.0, 5.0, 0.0)) * cube_transform.transform,
             );
         }
+        //Under the cubes
+        if let Some(plane_transform) = transforms[plane].as_mut() {
+            plane_transform.set_transform(Mat4::from_translation(Vec3::new(0.0, -60.0, 0.0)));
+        }
 
         drop(transforms);
         drop(scene_lock);
@@ -1,2 +1,6 @@
 pub mod fragment;
+pub mod lit_fragment;
+pub mod lit_vertex;
+pub mod textured_fragment;
+pub mod textured_vertex;
 pub mod vertex;
@@ -0,0 +1,91 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+// Blinn-Phong lighting shared by the lit fragment shaders. Expects the frame's lights at set 0
+// binding 1 and the material at set 1 binding 0.
+
+// Matches renderer_system::lighting::MAX_LIGHTS
+#define MAX_LIGHTS 16
+#define DIRECTIONAL 0
+#define POINT 1
+#define SPOT 2
+
+struct Light {
+    // xyz world position, w the light type
+    vec4 position;
+    // xyz world direction the light shines in, w range
+    vec4 direction;
+    // rgb colour times intensity, w cos of a spot's outer angle
+    vec4 color;
+    // x cos of a spot's inner angle
+    vec4 cone;
+};
+
+layout(set = 0, binding = 1) uniform LightData {
+    Light lights[MAX_LIGHTS];
+    vec4 ambient;
+    vec3 camera_position;
+    uint light_count;
+} light_data;
+
+// resource::materials::Material
+layout(set = 1, binding = 0) uniform MaterialData {
+    vec4 base_color;
+    vec3 emissive;
+    float roughness;
+    float metallic;
+} material;
+
+// Reflectance of non-metals seen head on
+const float DIELECTRIC_SPECULAR = 0.04;
+
+// Ambient plus every light reflected off a surface of albedo, n has to be normalized.
+// Emissive isn't included.
+vec3 shade(vec3 albedo, vec3 n, vec3 world_position) {
+    vec3 v = normalize(light_data.camera_position - world_position);
+    // Roughness 0 gives a tight highlight, 1 a broad dull one
+    float shininess = exp2(11.0 * (1.0 - clamp(material.roughness, 0.0, 1.0)));
+    // Normalised so rough surfaces don't reflect more light overall than smooth ones
+    float normalisation = (shininess + 8.0) / 8.0;
+    vec3 diffuse_color = albedo * (1.0 - material.metallic);
+    vec3 specular_color = mix(vec3(DIELECTRIC_SPECULAR), albedo, material.metallic);
+    vec3 lit = light_data.ambient.rgb * albedo;
+
+    for (uint i = 0; i < light_data.light_count; i++) {
+        Light light = light_data.lights[i];
+        int kind = int(light.position.w);
+        vec3 l;
+        float attenuation = 1.0;
+        if (kind == DIRECTIONAL) {
+            l = -normalize(light.direction.xyz);
+        } else {
+            vec3 to_light = light.position.xyz - world_position;
+            float dist = length(to_light);
+            l = to_light / max(dist, 1e-4);
+            // Smooth falloff reaching zero at the light's range
+            float falloff = clamp(1.0 - pow(dist / light.direction.w, 4.0), 0.0, 1.0);
+            attenuation = falloff * falloff;
+            if (kind == SPOT) {
+                float cos_angle = dot(-l, normalize(light.direction.xyz));
+                attenuation *= smoothstep(light.color.w, light.cone.x, cos_angle);
+            }
+        }
+        float diffuse = max(dot(n, l), 0.0);
+        vec3 h = normalize(l + v);
+        float specular = pow(max(dot(n, h), 0.0), shininess) * normalisation * diffuse;
+        lit += (diffuse_color * diffuse + specular_color * specular) * light.color.rgb * attenuation;
+    }
+    return lit;
+}
@@ -0,0 +1,42 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+vulkano_shaders::shader! {
+    ty: "fragment",
+    include: ["src/shaders/include"],
+    src: r"
+        #version 460
+
+        #include <lighting.glsl>
+
+        layout(location = 0) in vec3 v_color;
+        layout(location = 1) in vec3 v_world_position;
+        layout(location = 2) in vec3 v_normal;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            vec3 albedo = v_color * material.base_color.rgb;
+            float alpha = material.base_color.a;
+            // Meshes without normals can't be lit
+            if (dot(v_normal, v_normal) < 1e-8) {
+                f_color = vec4(albedo + material.emissive, alpha);
+                return;
+            }
+            vec3 lit = shade(albedo, normalize(v_normal), v_world_position);
+            f_color = vec4(lit + material.emissive, alpha);
+        }
+    ",
+}
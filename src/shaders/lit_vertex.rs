@@ -0,0 +1,48 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+vulkano_shaders::shader! {
+    ty: "vertex",
+    src: r"
+
+        #version 450
+
+        layout(location = 0) in vec3 position;
+        layout(location = 1) in vec3 color;
+        layout(location = 2) in vec3 normal;
+        // Per instance
+        layout(location = 3) in mat4 instance_model;
+        layout(location = 7) in vec4 instance_tint;
+        // Inverse transpose of the model matrix, keeps normals perpendicular under non uniform scale
+        layout(location = 8) in mat3 instance_normal;
+
+        layout(location = 0) out vec3 v_color;
+        layout(location = 1) out vec3 v_world_position;
+        layout(location = 2) out vec3 v_normal;
+
+        layout(set = 0, binding = 0) uniform CameraData {
+            mat4 view;
+            mat4 projection;
+        } camera;
+
+        void main() {
+            vec4 world_position = instance_model * vec4(position, 1.0);
+            v_color = color * instance_tint.rgb;
+            v_world_position = world_position.xyz;
+            v_normal = instance_normal * normal;
+            gl_Position = camera.projection * camera.view * world_position;
+        }
+    ",
+}
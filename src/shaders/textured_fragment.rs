@@ -0,0 +1,56 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+vulkano_shaders::shader! {
+    ty: "fragment",
+    include: ["src/shaders/include"],
+    src: r"
+        #version 460
+
+        #include <lighting.glsl>
+
+        layout(location = 0) in vec3 v_color;
+        layout(location = 1) in vec3 v_world_position;
+        layout(location = 2) in vec3 v_normal;
+        layout(location = 3) in vec2 v_uv;
+        layout(location = 4) in vec4 v_tangent;
+
+        layout(location = 0) out vec4 f_color;
+
+        layout(set = 1, binding = 1) uniform sampler2D albedo_map;
+        layout(set = 1, binding = 2) uniform sampler2D normal_map;
+
+        void main() {
+            vec4 albedo_texel = texture(albedo_map, v_uv);
+            vec3 albedo = v_color * material.base_color.rgb * albedo_texel.rgb;
+            float alpha = material.base_color.a * albedo_texel.a;
+            // Meshes without normals can't be lit
+            if (dot(v_normal, v_normal) < 1e-8) {
+                f_color = vec4(albedo + material.emissive, alpha);
+                return;
+            }
+            vec3 n = normalize(v_normal);
+            // Without tangents the normal map can't be oriented, so it's skipped
+            if (dot(v_tangent.xyz, v_tangent.xyz) > 1e-8) {
+                vec3 t = normalize(v_tangent.xyz - n * dot(n, v_tangent.xyz));
+                vec3 b = cross(n, t) * v_tangent.w;
+                vec3 mapped = texture(normal_map, v_uv).xyz * 2.0 - 1.0;
+                n = normalize(mat3(t, b, n) * mapped);
+            }
+            vec3 lit = shade(albedo, n, v_world_position);
+            f_color = vec4(lit + material.emissive, alpha);
+        }
+    ",
+}
@@ -21,19 +21,22 @@ vulkano_shaders::shader! {
         layout(location = 0) in vec3 position;
         layout(location = 1) in vec3 color;
         layout(location = 2) in vec3 normal;
+        // Per instance
+        layout(location = 3) in mat4 instance_model;
+        layout(location = 7) in vec4 instance_tint;
 
         layout(location = 0) out vec3 v_color;
 
-        layout(set = 0, binding = 0) uniform VPData {
-            mat4 model;
+        // Written once per frame and shared by every draw
+        layout(set = 0, binding = 0) uniform CameraData {
             mat4 view;
             mat4 projection;
-        } uniforms;
+        } camera;
 
         void main() {
-            v_color = color;
-            mat4 worldview = uniforms.view * uniforms.model;
-            gl_Position = uniforms.projection * worldview * vec4(position, 1.0);
+            v_color = color * instance_tint.rgb;
+            mat4 worldview = camera.view * instance_model;
+            gl_Position = camera.projection * worldview * vec4(position, 1.0);
         }
     ",
 }
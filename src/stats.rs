@@ -0,0 +1,226 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+const CSV_HEADER: &str = "frame,frame_time_ms,controller_ms,render_record_ms,draw_calls,triangles,state_changes,entities";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameSample {
+    pub frame: u64,
+    //Wall clock time since the previous frame started
+    pub frame_time: Duration,
+    pub controller_time: Duration,
+    //CPU time spent building the frame's command buffer, not GPU time
+    pub render_record_time: Duration,
+    pub draw_calls: u32,
+    pub triangles: u64,
+    //Pipeline, descriptor set and buffer binds
+    pub state_changes: u32,
+    pub entities: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl Summary {
+    pub fn from_values(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        Some(Summary {
+            min: values[0],
+            avg: values.iter().sum::<f64>() / values.len() as f64,
+            max: values[values.len() - 1],
+            p50: percentile(&values, 50.0),
+            p95: percentile(&values, 95.0),
+            p99: percentile(&values, 99.0),
+        })
+    }
+}
+
+//Nearest rank on already sorted values
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let rank = (percent / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+//Rolling window of per frame samples, logs a summary every interval and optionally
+//writes every sample to a CSV file for graphing
+pub struct FrameStats {
+    samples: VecDeque<FrameSample>,
+    window: usize,
+    summary_interval: Option<Duration>,
+    last_summary: Instant,
+    csv: Option<BufWriter<File>>,
+}
+
+impl FrameStats {
+    pub fn new(window: usize) -> Self {
+        FrameStats {
+            samples: VecDeque::with_capacity(window),
+            window: window.max(1),
+            summary_interval: Some(Duration::from_secs(5)),
+            last_summary: Instant::now(),
+            csv: None,
+        }
+    }
+
+    //None turns the periodic summary off
+    pub fn with_summary_interval(mut self, summary_interval: Option<Duration>) -> Self {
+        self.summary_interval = summary_interval;
+        self
+    }
+
+    pub fn with_csv(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create stats file {}", path.display()))?;
+        let mut csv = BufWriter::new(file);
+        writeln!(csv, "{CSV_HEADER}")?;
+        self.csv = Some(csv);
+        Ok(self)
+    }
+
+    pub fn record(&mut self, sample: FrameSample) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.write_csv(&sample);
+
+        if let Some(interval) = self.summary_interval {
+            if self.last_summary.elapsed() >= interval {
+                self.log_summary();
+                self.flush();
+                self.last_summary = Instant::now();
+            }
+        }
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &FrameSample> {
+        self.samples.iter()
+    }
+
+    pub fn latest(&self) -> Option<&FrameSample> {
+        self.samples.back()
+    }
+
+    pub fn summary(&self, field: impl Fn(&FrameSample) -> f64) -> Option<Summary> {
+        Summary::from_values(self.samples.iter().map(field).collect())
+    }
+
+    pub fn frame_time_ms(&self) -> Option<Summary> {
+        self.summary(|sample| millis(sample.frame_time))
+    }
+
+    pub fn controller_ms(&self) -> Option<Summary> {
+        self.summary(|sample| millis(sample.controller_time))
+    }
+
+    pub fn render_record_ms(&self) -> Option<Summary> {
+        self.summary(|sample| millis(sample.render_record_time))
+    }
+
+    pub fn log_summary(&self) {
+        let (Some(frame), Some(controller), Some(render), Some(latest)) = (
+            self.frame_time_ms(),
+            self.controller_ms(),
+            self.render_record_ms(),
+            self.latest(),
+        ) else {
+            return;
+        };
+        info!(
+            "Frame ms min/avg/max {:.2}/{:.2}/{:.2} p95 {:.2} p99 {:.2} ({:.1} fps) | controllers avg {:.2} p99 {:.2} | render record avg {:.2} p99 {:.2} | {} draws, {} tris, {} state changes, {} entities",
+            frame.min,
+            frame.avg,
+            frame.max,
+            frame.p95,
+            frame.p99,
+            if frame.avg > 0.0 { 1000.0 / frame.avg } else { 0.0 },
+            controller.avg,
+            controller.p99,
+            render.avg,
+            render.p99,
+            latest.draw_calls,
+            latest.triangles,
+            latest.state_changes,
+            latest.entities,
+        );
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(csv) = self.csv.as_mut() {
+            if let Err(err) = csv.flush() {
+                warn!("Failed to flush stats file, stopping CSV export: {err}");
+                self.csv = None;
+            }
+        }
+    }
+
+    fn write_csv(&mut self, sample: &FrameSample) {
+        let Some(csv) = self.csv.as_mut() else {
+            return;
+        };
+        let result = writeln!(
+            csv,
+            "{},{:.4},{:.4},{:.4},{},{},{},{}",
+            sample.frame,
+            millis(sample.frame_time),
+            millis(sample.controller_time),
+            millis(sample.render_record_time),
+            sample.draw_calls,
+            sample.triangles,
+            sample.state_changes,
+            sample.entities,
+        );
+        if let Err(err) = result {
+            warn!("Failed to write stats file, stopping CSV export: {err}");
+            self.csv = None;
+        }
+    }
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        FrameStats::new(600)
+    }
+}
+
+impl Drop for FrameStats {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
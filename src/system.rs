@@ -1,2 +1,20 @@
 pub mod renderer_system;
-pub mod controller_system;
\ No newline at end of file
+pub mod controller_system;
+pub mod simulation_system;
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::scene::Scene;
+
+//Game wide logic that isn't tied to one entity, run every frame after the controllers.
+//Added through AppBuilder::add_system.
+pub trait System: Send + Sync {
+    fn run(&mut self, scene: &Arc<RwLock<Scene>>, delta: Duration) -> Result<()>;
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
@@ -13,29 +13,110 @@
 //limitations under the License.
 
 
-use std::sync::{Arc, RwLock};
+use std::any::Any;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, RwLock};
 
 use std::thread;
 use rayon::prelude::*;
-use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
-use crate::{app::Input, component::controller::Controller, scene::{Scene}};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tracing::{debug, debug_span, error, info, info_span};
+use anyhow::Result;
+use crate::{
+    component::controller::Controller,
+    resource::mailbox::Mailbox,
+    scene::{lock::RecoverLock, Scene},
+};
 
 
 pub struct ControllerSystem {
-   
+    //Entity indices of controllers that errored or panicked, they are skipped until re-enabled
+    disabled: Mutex<HashSet<usize>>,
 }
 
 impl ControllerSystem {
 
     pub fn new() -> Self {
       
-        Self {}
+        Self {
+            disabled: Mutex::new(HashSet::new()),
+        }
     }
 
-    pub fn run(&self, scene: Arc<RwLock<Scene>>, current_input: Input) {
+    pub fn is_disabled(&self, index: usize) -> bool {
+        self.disabled
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains(&index)
+    }
+
+    pub fn disabled_controllers(&self) -> Vec<usize> {
+        let mut disabled: Vec<usize> = self
+            .disabled
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .copied()
+            .collect();
+        disabled.sort_unstable();
+        disabled
+    }
+
+    pub fn enable(&self, index: usize) {
+        if self
+            .disabled
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&index)
+        {
+            info!(entity = index, "Controller re-enabled");
+        }
+    }
+
+    fn disable(&self, index: usize) {
+        self.disabled
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(index);
+    }
+
+    //Catches errors and panics per controller so one bad entity can't take down the frame,
+    //the controller is disabled instead
+    fn guarded(&self, index: usize, stage: &str, callback: impl FnOnce() -> Result<()>) {
+        match panic::catch_unwind(AssertUnwindSafe(callback)) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                error!(entity = index, "Controller {stage} failed, disabling it: {err:#}");
+                self.disable(index);
+            }
+            Err(payload) => {
+                error!(
+                    entity = index,
+                    "Controller panicked in {stage}, disabling it: {}",
+                    panic_message(payload.as_ref())
+                );
+                self.disable(index);
+            }
+        }
+    }
+
+    pub fn run(&self, scene: Arc<RwLock<Scene>>) {
+        let _span = info_span!("controller_system").entered();
         //let mut thread_join_handle;
-        let binding = scene.read().unwrap().get_component_vec::<Arc<RwLock<Box<dyn Controller>>>>().unwrap();
-        let controllers = binding.read().unwrap().clone();
+        //Messages sent last frame, anything sent during this run waits for the next one
+        let mailbox = scene.write_or_recover().get_or_insert_resource::<Mailbox>();
+        let inbox = mailbox.write_or_recover().take();
+
+        let Some(binding) = scene.read_or_recover().get_component_vec::<Arc<RwLock<Box<dyn Controller>>>>() else {
+            return;
+        };
+        let controllers = binding.read_or_recover().clone();
+        for (target, messages) in inbox.iter() {
+            if controllers.get(*target).map_or(true, Option::is_none) {
+                debug!(entity = *target, "Dropping {} message(s) for entity without a controller", messages.len());
+            }
+        }
         
      
 
@@ -48,13 +129,41 @@ impl ControllerSystem {
         //}
 
         //let controllers: Vec<Arc<RwLock<Box<dyn Controller+ 'static>>>> = controllers.into_iter().filter_map(|controller: Option<Arc<RwLock<Box<dyn Controller>>>>| Some(controller)?).collect();
-        controllers.into_par_iter().enumerate().for_each(|(index, controller)|{
+        controllers.par_iter().enumerate().for_each(|(index, controller)|{
             //let new_scene = scene.clone();
             if let Some(controller) = controller {
-                controller.write().unwrap().update(index, scene.clone());
+                if self.is_disabled(index) {
+                    return;
+                }
+                let _span = debug_span!("controller", entity = index).entered();
+                self.guarded(index, "update", || {
+                    let mut controller = controller.write_or_recover();
+                    if let Some(messages) = inbox.get(&index) {
+                        let _span = debug_span!("on_message", count = messages.len()).entered();
+                        for message in messages {
+                            controller.on_message(index, message, scene.clone())?;
+                        }
+                    }
+                    let _span = debug_span!("update").entered();
+                    controller.update(index, scene.clone())
+                });
             }
         });
 
+        //Sequential and in entity order so replays see the same frame every time
+        let _span = debug_span!("late_update").entered();
+        for (index, controller) in controllers.iter().enumerate() {
+            let Some(controller) = controller else {
+                continue;
+            };
+            if self.is_disabled(index) {
+                continue;
+            }
+            self.guarded(index, "late update", || {
+                controller.write_or_recover().late_update(index, scene.clone())
+            });
+        }
+
 
             //if let Some(controller) = controller {
             //    controller.write().unwrap().update(index, scene.clone());
@@ -131,3 +240,13 @@ impl ControllerSystem {
         //info!("Game loop deb3");
     }
 }
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
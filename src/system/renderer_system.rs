@@ -13,41 +13,58 @@
 //limitations under the License.
 
 
-mod pipelines;
+pub mod lighting;
+pub mod mesh_cache;
+pub mod pipelines;
+pub mod render_queue;
+pub mod texture_cache;
 use crate::app::UserEvent;
+use crate::config::EngineConfig;
 use crate::component::camera_component::CameraComponent;
-use crate::component::mesh_filter_component::{MeshFilterComponent};
-use crate::component::mesh_renderer_component::{MeshRendererComponent};
+use crate::component::instanced_mesh_component::InstancedMesh;
+use crate::component::mesh_filter_component::{MeshFilterComponent, MeshId};
+use crate::component::mesh_renderer_component::{DrawPass, MeshRendererComponent};
 use crate::component::transform_component::{TransformComponent};
 
+use crate::geometry::vertex::InstanceData;
+use crate::scene::lock::RecoverLock;
 use crate::scene::Scene;
+use crate::resource::materials::{Material, MaterialHandle, Materials};
+use crate::shaders::lit_fragment::{LightData, MaterialData};
+use crate::shaders::vertex::CameraData;
 use itertools::izip;
+use mesh_cache::{GpuMesh, MeshCache};
+use texture_cache::{TextureCache, TextureKind};
+use render_queue::{DrawBatch, RenderQueue};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use glam::Mat4;
-use pipelines::lines::{Lines, LinesExtra};
-//use pipelines::lines::{Lines, LinesExtra};
-use pipelines::teapot::{Teapot, TeapotExtra};
-use pipelines::GraphicsPipelineWrapper;
+use pipelines::blinn_phong::BlinnPhong;
+use pipelines::lines::Lines;
+use pipelines::teapot::Teapot;
+use pipelines::teapot_v2::TeapotV2;
+use pipelines::textured::Textured;
+use pipelines::{FrameUniforms, MaterialResources, RenderPipeline, INSTANCE_BINDING};
 use vulkano::format::Format;
 
-use std::any::Any;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{error, info, info_span, warn};
 use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
-use vulkano::buffer::BufferUsage;
+use vulkano::buffer::{BufferUsage, Subbuffer};
 use vulkano::command_buffer::allocator::{CommandBufferAlloc, StandardCommandBufferAllocator};
 use vulkano::command_buffer::{
-    AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo,
+    AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
 };
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::PersistentDescriptorSet;
 use vulkano::device::physical::PhysicalDeviceType;
 use vulkano::device::{
     Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
 };
 use vulkano::image::view::ImageView;
-use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
 use vulkano::instance::{Instance, InstanceCreateFlags, InstanceCreateInfo};
 use vulkano::memory::allocator::{
     AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator,
@@ -69,8 +86,19 @@ pub struct RendererSystem {
     device: Arc<Device>,
     memory_allocator: Arc<StandardMemoryAllocator>,
     uniform_buffer_allocator: SubbufferAllocator,
+    instance_buffer_allocator: SubbufferAllocator,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
-    pipelines: HashMap<String, Box<dyn Any>>,
+    pipelines: HashMap<String, Box<dyn RenderPipeline>>,
+    //Keys meshes asked for that were never registered, each is only warned about once
+    unknown_pipeline_keys: HashSet<String>,
+    //Why each entity that can't be drawn was last warned about, warned again if the reason
+    //changes and forgotten once it's drawn
+    incompatible_entities: HashMap<usize, String>,
+    //Materials whose uniform or textures couldn't be prepared, warned about once until
+    //they succeed again
+    failed_materials: HashSet<MaterialHandle>,
+    mesh_cache: MeshCache,
+    texture_cache: TextureCache,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
     queue: Arc<Queue>,
     pub recreate_swapchain: bool,
@@ -81,7 +109,24 @@ pub struct RendererSystem {
     first_camera_update: bool, //viewport: Viewport,
     renderpass: Option<Arc<RenderPass>>,
     depth_buffer: Option<Arc<ImageView>>,
-    frame_buffers: Option<Vec<Arc<Framebuffer>>>
+    //Multisampled colour target resolved into the swapchain image, None without MSAA
+    msaa_color_buffer: Option<Arc<ImageView>>,
+    frame_buffers: Option<Vec<Arc<Framebuffer>>>,
+    samples: SampleCount,
+    clear_color: [f32; 4],
+}
+
+//What the last redraw submitted, zeroed when a frame is skipped
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    //Meshes new or changed since the last frame, copied to the GPU this frame
+    pub mesh_uploads: u32,
+    //Pipeline, descriptor set and vertex/index buffer binds
+    pub state_changes: u32,
+    //CPU time recording and building the command buffer
+    pub record_time: Duration,
 }
 
 impl RendererSystem {
@@ -89,6 +134,7 @@ impl RendererSystem {
         window: Arc<Window>,
         event_loop: &EventLoop<UserEvent>,
         scene: Arc<RwLock<Scene>>,
+        config: &EngineConfig,
     ) -> Result<Self> {
         let required_extensions = Surface::required_extensions(event_loop);
         let library = VulkanLibrary::new().unwrap();
@@ -146,6 +192,11 @@ impl RendererSystem {
         if physical_device.api_version() < Version::V1_3 {
             device_extensions.khr_dynamic_rendering = true;
         }
+        //Optional, textures are sampled without anisotropy and BCn KTX2 files fail to load
+        //without them
+        let supported_features = physical_device.supported_features();
+        let sampler_anisotropy = supported_features.sampler_anisotropy;
+        let texture_compression_bc = supported_features.texture_compression_bc;
 
         let (device, mut queues) = Device::new(
             physical_device,
@@ -159,6 +210,8 @@ impl RendererSystem {
                     dynamic_rendering: true,
                     fill_mode_non_solid: true,
                     wide_lines: true,
+                    sampler_anisotropy,
+                    texture_compression_bc,
                     ..Features::empty()
                 },
                 ..Default::default()
@@ -179,6 +232,13 @@ impl RendererSystem {
                 .unwrap()[0]
                 .0;
 
+            let supported_present_modes = device
+                .physical_device()
+                .surface_present_modes(&surface, Default::default())
+                .unwrap();
+            let present_mode = config.display.present_mode.choose(supported_present_modes);
+            info!("Using present mode {:?}", present_mode);
+
             Swapchain::new(
                 device.clone(),
                 surface.clone(),
@@ -192,13 +252,18 @@ impl RendererSystem {
                         .into_iter()
                         .next()
                         .unwrap(),
-
+                    present_mode,
                     ..Default::default()
                 },
             )
             .unwrap()
         };
 
+        let samples = supported_sample_count(&device, config.msaa_samples);
+        if u32::from(samples) != config.msaa_samples {
+            warn!("{}x MSAA isn't supported, using {:?}", config.msaa_samples, samples);
+        }
+
         let memory_allocator: Arc<StandardMemoryAllocator> =
             Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
@@ -211,13 +276,22 @@ impl RendererSystem {
                 ..Default::default()
             },
         );
+        let instance_buffer_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::VERTEX_BUFFER,
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+        );
         //let pipeline_default = pipelines::create_pipeline_default(device.clone(), surface.clone(), swapchain.clone());
-        //let vec: Box<dyn GraphicsPipelineWrapper> = vec![];
-        let pipeline_teapot = Box::new(Teapot::new(device.clone()).unwrap()) as Box<dyn Any>;
-        let pipeline_lines = Box::new(Lines::new(device.clone()).unwrap()) as Box<dyn Any>;
-        let mut pipelines = HashMap::new();
-        pipelines.insert(String::from("teapot"), pipeline_teapot);
-        pipelines.insert(String::from("lines"), pipeline_lines);
+        let mut pipelines: HashMap<String, Box<dyn RenderPipeline>> = HashMap::new();
+        pipelines.insert(String::from("teapot"), Box::new(Teapot::new(device.clone())?));
+        pipelines.insert(String::from("lines"), Box::new(Lines::new(device.clone())?));
+        pipelines.insert(String::from("teapot_v2"), Box::new(TeapotV2::new(device.clone())?));
+        pipelines.insert(String::from("lit"), Box::new(BlinnPhong::new(device.clone())?));
+        pipelines.insert(String::from("textured"), Box::new(Textured::new(device.clone())?));
 
         let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
             device.clone(),
@@ -225,6 +299,8 @@ impl RendererSystem {
         ));
         let command_buffer_allocator =
             StandardCommandBufferAllocator::new(device.clone(), Default::default());
+        let mesh_cache = MeshCache::new(memory_allocator.clone());
+        let texture_cache = TextureCache::new(device.clone(), memory_allocator.clone());
 
         let recreate_swapchain = true;
         let previous_frame_end = Some(sync::now(device.clone()).boxed());
@@ -235,28 +311,66 @@ impl RendererSystem {
             command_buffer_allocator,
             memory_allocator,
             uniform_buffer_allocator,
+            instance_buffer_allocator,
             descriptor_set_allocator,
             recreate_swapchain,
             previous_frame_end,
             images,
             pipelines,
+            unknown_pipeline_keys: HashSet::new(),
+            incompatible_entities: HashMap::new(),
+            failed_materials: HashSet::new(),
+            mesh_cache,
+            texture_cache,
             device,
             current_scene: Arc::clone(&scene),
             current_window: Arc::clone(&window),
             first_camera_update: false,
             renderpass: None,
             depth_buffer: None,
-            frame_buffers: None
+            msaa_color_buffer: None,
+            frame_buffers: None,
+            samples,
+            clear_color: config.clear_color,
 
         })
     }
 
-    pub fn redraw(&mut self) {
+    //Makes pipeline available to materials with this pipeline_key, replacing any
+    //pipeline already registered under it. One that fails to create isn't registered.
+    pub fn register_pipeline(&mut self, key: &str, mut pipeline: Box<dyn RenderPipeline>) {
+        if let Some(render_pass) = self.renderpass.as_ref() {
+            let created =
+                pipeline.create(self.device.clone(), render_pass.clone(), self.swapchain.image_extent());
+            if let Err(err) = created {
+                error!("Failed to create pipeline {key} ({}), not registering it: {err:#}", pipeline.name());
+                return;
+            }
+        }
+        if self.pipelines.insert(key.to_string(), pipeline).is_some() {
+            info!("Replaced pipeline {key}");
+        }
+        self.unknown_pipeline_keys.remove(key);
+    }
+
+    pub fn has_pipeline(&self, key: &str) -> bool {
+        self.pipelines.contains_key(key)
+    }
+
+    //For loading shaders when creating pipelines to register
+    pub fn device(&self) -> Arc<Device> {
+        self.device.clone()
+    }
+
+    pub fn redraw(&mut self) -> RenderStats {
+        let _span = info_span!("redraw").entered();
+        let mut stats = RenderStats::default();
         let image_extent: [u32; 2] = self.current_window.inner_size().into();
         let mut aspect_ratio =
             self.swapchain.image_extent()[0] as f32 / self.swapchain.image_extent()[1] as f32;
         self.previous_frame_end.as_mut().unwrap().cleanup_finished();
         if self.recreate_swapchain {
+            let _span = info_span!("recreate_swapchain").entered();
             let (new_swapchain, new_images) = self
                 .swapchain
                 .recreate(SwapchainCreateInfo {
@@ -269,28 +383,80 @@ impl RendererSystem {
             self.images = new_images;
             // Need to update aspect ratio
             aspect_ratio = self.swapchain.image_extent()[0] as f32 / self.swapchain.image_extent()[1] as f32;
-            self.renderpass =  Some(vulkano::single_pass_renderpass!(
-                self.device.clone(),
-                attachments: {
-                    color: {
-                        format: self.swapchain.image_format(),
-                        samples: 1,
-                        load_op: Clear,
-                        store_op: Store,
+            let samples = u32::from(self.samples);
+            self.renderpass = Some(if self.samples == SampleCount::Sample1 {
+                vulkano::single_pass_renderpass!(
+                    self.device.clone(),
+                    attachments: {
+                        color: {
+                            format: self.swapchain.image_format(),
+                            samples: 1,
+                            load_op: Clear,
+                            store_op: Store,
+                        },
+                        depth_stencil: {
+                            format: Format::D16_UNORM,
+                            samples: 1,
+                            load_op: Clear,
+                            store_op: DontCare,
+                        },
                     },
-                    depth_stencil: {
-                        format: Format::D16_UNORM,
-                        samples: 1,
-                        load_op: Clear,
-                        store_op: DontCare,
+                    pass: {
+                        color: [color],
+                        depth_stencil: {depth_stencil},
                     },
-                },
-                pass: {
-                    color: [color],
-                    depth_stencil: {depth_stencil},
-                },
-            )
-            .unwrap());
+                )
+                .unwrap()
+            } else {
+                vulkano::single_pass_renderpass!(
+                    self.device.clone(),
+                    attachments: {
+                        msaa_color: {
+                            format: self.swapchain.image_format(),
+                            samples: samples,
+                            load_op: Clear,
+                            store_op: DontCare,
+                        },
+                        color: {
+                            format: self.swapchain.image_format(),
+                            samples: 1,
+                            load_op: DontCare,
+                            store_op: Store,
+                        },
+                        depth_stencil: {
+                            format: Format::D16_UNORM,
+                            samples: samples,
+                            load_op: Clear,
+                            store_op: DontCare,
+                        },
+                    },
+                    pass: {
+                        color: [msaa_color],
+                        color_resolve: [color],
+                        depth_stencil: {depth_stencil},
+                    },
+                )
+                .unwrap()
+            });
+
+            self.msaa_color_buffer = (self.samples != SampleCount::Sample1).then(|| {
+                ImageView::new_default(
+                    Image::new(
+                        self.memory_allocator.clone(),
+                        ImageCreateInfo {
+                            image_type: ImageType::Dim2d,
+                            format: self.swapchain.image_format(),
+                            extent: self.images[0].extent(),
+                            samples: self.samples,
+                            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo::default(),
+                    )
+                    .unwrap(),
+                )
+                .unwrap()
+            });
 
                 //Only need this to include it in framebuffers ???
             self.depth_buffer = Some(ImageView::new_default(
@@ -300,6 +466,7 @@ impl RendererSystem {
                         image_type: ImageType::Dim2d,
                         format: Format::D16_UNORM,
                         extent: self.images[0].extent(),
+                        samples: self.samples,
                         usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
                         ..Default::default()
                     },
@@ -314,10 +481,16 @@ impl RendererSystem {
                 .iter()
                 .map(|image| {
                     let view = ImageView::new_default(image.clone()).unwrap();
+                    let depth = self.depth_buffer.as_ref().unwrap().clone();
+                    //Same order as the render pass attachments
+                    let attachments = match self.msaa_color_buffer.as_ref() {
+                        Some(msaa_color) => vec![msaa_color.clone(), view, depth],
+                        None => vec![view, depth],
+                    };
                     Framebuffer::new(
                         self.renderpass.as_mut().unwrap().clone(),
                         FramebufferCreateInfo {
-                            attachments: vec![view, self.depth_buffer.as_ref().unwrap().clone()],
+                            attachments,
                             ..Default::default()
                         },
                     )
@@ -325,26 +498,30 @@ impl RendererSystem {
                 })
                 .collect::<Vec<_>>());
 
-            for (index, pipeline) in self.pipelines.iter_mut() {
-                if let Some(pipeline) = pipeline.downcast_mut::<Teapot>() {
-                    info!("Teapot renderer being created");
-                    pipeline.create(self.device.clone(), self.renderpass.as_ref().unwrap().clone(), image_extent);
-                } else if let Some(pipeline) = pipeline.downcast_mut::<Lines>() {
-                    info!("Lines renderer being created");
-                    pipeline.create(self.device.clone(), self.renderpass.as_ref().unwrap().clone(), image_extent);
+            let render_pass = self.renderpass.as_ref().unwrap().clone();
+            self.pipelines.retain(|key, pipeline| {
+                info!("Creating pipeline {} ({})", key, pipeline.name());
+                match pipeline.create(self.device.clone(), render_pass.clone(), image_extent) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        error!("Failed to create pipeline {key} ({}), dropping it: {err:#}", pipeline.name());
+                        false
+                    }
                 }
-                //Update active camera perspectives
-            }
+            });
+            //Update active camera perspectives
             update_camera_perspective(self.current_scene.clone(), aspect_ratio);
 
             self.recreate_swapchain = false;
         }
+        let acquired = info_span!("acquire_image")
+            .in_scope(|| acquire_next_image(self.swapchain.clone(), None).map_err(Validated::unwrap));
         let (image_index, suboptimal, acquire_future) =
-            match acquire_next_image(self.swapchain.clone(), None).map_err(Validated::unwrap) {
+            match acquired {
                 Ok(r) => r,
                 Err(VulkanError::OutOfDate) => {
                     self.recreate_swapchain = true;
-                    return;
+                    return stats;
                 }
                 Err(e) => panic!("failed to acquire next image: {e}"),
             };
@@ -362,23 +539,37 @@ impl RendererSystem {
         //let proj = Perspective3::new(image_extent[0] as f32/ image_extent[1] as f32, fovy, znear, zfar);
         //let vertices = generate_vertices(self.current_scene.clone());
 
-        let current_scene = self.current_scene.read().unwrap();
+        let current_scene = self.current_scene.read_or_recover();
 
         let binding = current_scene
             .get_component_vec::<TransformComponent>()
             .unwrap();
         //info!("Renderer transform lock");
-        let transforms = binding.read().unwrap();
+        let transforms = binding.read_or_recover();
 
         let binding = current_scene
             .get_component_vec::<MeshFilterComponent>()
             .unwrap();
-        let mesh_filters = binding.read().unwrap();
+        let mesh_filters = binding.read_or_recover();
 
         let binding = current_scene
             .get_component_vec::<MeshRendererComponent>()
             .unwrap();
-        let mesh_renderers = binding.read().unwrap();
+        let mesh_renderers = binding.read_or_recover();
+
+        let instanced_binding = current_scene.get_component_vec::<InstancedMesh>();
+        let instanced_meshes = instanced_binding.as_ref().map(|binding| binding.read_or_recover());
+
+        //Handles from a scene without materials, or stale ones, draw with the default
+        let materials_binding = current_scene.get_resource::<Materials>();
+        let materials = materials_binding.as_ref().map(|binding| binding.read_or_recover());
+        let default_material = Material::default();
+        let material_of = |handle: MaterialHandle| {
+            materials
+                .as_ref()
+                .and_then(|materials| materials.get(handle))
+                .unwrap_or(&default_material)
+        };
 
         //let mut mesh_filters = current_scene.borrow_component_vec_mut::<MeshFilterComponent>().unwrap();
         let zip = izip!(
@@ -387,14 +578,17 @@ impl RendererSystem {
             mesh_renderers.iter()
         );
 
-        let iter = zip.filter_map(|(transform, mesh_filter, mesh_renderer)| {
+        let iter = zip.enumerate().filter_map(|(entity, (transform, mesh_filter, mesh_renderer))| {
             Some((
+                entity,
                 transform.as_ref()?,
                 mesh_filter.as_ref()?,
                 mesh_renderer.as_ref()?,
             ))
         });
 
+        let record_started = Instant::now();
+        let record_span = info_span!("record_command_buffer").entered();
         let mut builder: AutoCommandBufferBuilder<
             vulkano::command_buffer::PrimaryAutoCommandBuffer,
         > = AutoCommandBufferBuilder::primary(
@@ -404,59 +598,166 @@ impl RendererSystem {
         )
         .unwrap();
 
+        //Sorted then cut into batches of entities sharing a pass, pipeline and mesh, each
+        //batch is one instanced draw
+        let mut queue = RenderQueue::new();
+        for (entity, transform_component, mesh_filter_component, mesh_renderer_component) in iter {
+            let material = mesh_renderer_component.material;
+            let pipeline_key = material_of(material).pipeline_key.as_str();
+            let Some(pipeline) = self.pipelines.get(pipeline_key) else {
+                if self.unknown_pipeline_keys.insert(pipeline_key.to_string()) {
+                    warn!("Entity {entity} uses unknown pipeline {pipeline_key:?}, it won't be drawn");
+                }
+                continue;
+            };
+            let missing = mesh_filter_component
+                .mesh()
+                .missing_attributes(pipeline.vertex_attributes());
+            if !missing.is_empty() {
+                warn_incompatible(
+                    &mut self.incompatible_entities,
+                    entity,
+                    format!("Entity {entity}'s mesh has no {missing:?}, pipeline {pipeline_key:?} can't draw it"),
+                );
+                continue;
+            }
+            let transparent = mesh_renderer_component.pass == DrawPass::Transparent;
+            if transparent && pipeline.transparent_pipeline().is_none() {
+                warn_incompatible(
+                    &mut self.incompatible_entities,
+                    entity,
+                    format!("Entity {entity} is transparent, pipeline {pipeline_key:?} can't blend it"),
+                );
+                continue;
+            }
+            self.incompatible_entities.remove(&entity);
+            let model = transform_component.transform;
+            let tint = mesh_renderer_component.tint;
+            let depth = view.transform_point3(model.w_axis.truncate()).z;
+            let instanced_mesh =
+                instanced_meshes.as_ref().and_then(|instanced| instanced.get(entity)?.as_ref());
+            match instanced_mesh {
+                Some(instanced_mesh) => queue.push(
+                    mesh_renderer_component.pass,
+                    pipeline_key,
+                    material,
+                    mesh_filter_component,
+                    depth,
+                    instanced_mesh
+                        .instances
+                        .iter()
+                        .map(|instance| InstanceData::new(model * instance.transform, tint * instance.tint)),
+                ),
+                None => queue.push(
+                    mesh_renderer_component.pass,
+                    pipeline_key,
+                    material,
+                    mesh_filter_component,
+                    depth,
+                    [InstanceData::new(model, tint)],
+                ),
+            }
+        }
+        queue.sort();
+
+        //Mesh uploads are copies so have to be recorded before the render pass begins
+        let mut batches = Vec::new();
+        for batch in queue.batches() {
+            match self.mesh_cache.get_or_upload(batch.mesh, &mut builder) {
+                Ok(Some(mesh)) => batches.push((batch, mesh)),
+                Ok(None) => {}
+                Err(err) => warn!("Failed to upload mesh: {err:#}"),
+            }
+        }
+        stats.mesh_uploads = self.mesh_cache.end_frame();
+
+        //A uniform and textures for each material drawn this frame, textures are uploaded
+        //the first time they're used so this also has to come before the render pass
+        let mut materials_drawn = HashMap::new();
+        for (batch, _) in &batches {
+            if materials_drawn.contains_key(&batch.material) {
+                continue;
+            }
+            let resources = material_resources(
+                material_of(batch.material),
+                &self.uniform_buffer_allocator,
+                &mut self.texture_cache,
+                &mut builder,
+            );
+            match resources {
+                Ok(resources) => {
+                    self.failed_materials.remove(&batch.material);
+                    materials_drawn.insert(batch.material, resources);
+                }
+                Err(err) => {
+                    if self.failed_materials.insert(batch.material) {
+                        warn!("Failed to prepare material {:?}: {err:#}", batch.material);
+                    }
+                }
+            }
+        }
+        batches.retain(|(batch, _)| materials_drawn.contains_key(&batch.material));
+
         builder
             .begin_render_pass(
                 RenderPassBeginInfo {
-                    clear_values: vec![Some([1.0, 1.0, 1.0, 1.0].into()), Some(1f32.into())],
+                    clear_values: if self.msaa_color_buffer.is_some() {
+                        vec![Some(self.clear_color.into()), None, Some(1f32.into())]
+                    } else {
+                        vec![Some(self.clear_color.into()), Some(1f32.into())]
+                    },
                     ..RenderPassBeginInfo::framebuffer(self.frame_buffers.as_ref().unwrap()[image_index as usize].clone())
                 },
                 Default::default(),
             )
             .unwrap();
 
-        for (transform_component, mesh_filter_component, mesh_renderer_component) in iter {
-            //info!("Object transform: {:?}", transform_component.transform);
-            if let Some(target_pipeline) = self.pipelines.get(&mesh_renderer_component.pipeline_key)
-            {
-                if let Some(renderer) = target_pipeline.downcast_ref::<Teapot>() {
-                    renderer.render(
-                        mesh_filter_component.indexed_verts.verts.clone(),
-                        &TeapotExtra {
-                            indices: mesh_filter_component.indexed_verts.indices.clone(),
-                            model: transform_component.transform,
-                            view: view,
-                            proj: proj,
-                        },
-                        self.memory_allocator.clone(),
-                        self.descriptor_set_allocator.clone(),
-                        &mut builder,
-                    );
-                } else if let Some(renderer) = target_pipeline.downcast_ref::<Lines>() {
-                    //info!("Here");
-                    renderer.render(
-                        mesh_filter_component.indexed_verts.verts.clone(),
-                        &LinesExtra {
-                            indices: mesh_filter_component.indexed_verts.indices.clone(),
-                            model: transform_component.transform,
-                            view: view,
-                            proj: proj,
-                        },
-                        self.memory_allocator.clone(),
-                        self.descriptor_set_allocator.clone(),
-                        &mut builder,
-                    );
-                }
+        //One camera uniform, one light uniform and one instance buffer for the whole frame,
+        //each batch draws a range of the instance buffer
+        let camera = self.uniform_buffer_allocator.allocate_sized::<CameraData>().unwrap();
+        *camera.write().unwrap() = CameraData {
+            view: view.to_cols_array_2d(),
+            projection: proj.to_cols_array_2d(),
+        };
+        let lights = self.uniform_buffer_allocator.allocate_sized::<LightData>().unwrap();
+        *lights.write().unwrap() =
+            lighting::gather_lights(&current_scene, &transforms, view.inverse().w_axis.truncate());
+        let instance_buffer = (!queue.is_empty()).then(|| {
+            let buffer = self
+                .instance_buffer_allocator
+                .allocate_slice::<InstanceData>(queue.instance_count() as u64)
+                .unwrap();
+            queue.write_instances(&mut buffer.write().unwrap());
+            buffer
+        });
+        let mut bindings =
+            FrameBindings::new(FrameUniforms { camera, lights }, materials_drawn, instance_buffer);
 
-                /*if let Some(mfv) = mesh_filter_component.downcast_ref::<MeshFilterComponent<Vertex>>(){
-                    if let Some(renderer) = target.downcast_ref::<VertexRenderer>(){
-                        renderer.render(mfv, ());
-                    }
-                } */
+        for (batch, mesh) in batches {
+            let Some(pipeline) = self.pipelines.get(batch.pipeline_key) else {
+                continue;
+            };
+            let drawn = bindings.draw(
+                &batch,
+                pipeline.as_ref(),
+                &mesh,
+                &self.descriptor_set_allocator,
+                &mut builder,
+            );
+            if let Err(err) = drawn {
+                warn!("Failed to draw with pipeline {}: {err:#}", batch.pipeline_key);
+                continue;
             }
+            stats.draw_calls += 1;
+            stats.triangles += mesh.index_count() as u64 / 3 * batch.instances.len() as u64;
         }
+        stats.state_changes = bindings.state_changes;
         builder.end_render_pass(Default::default()).unwrap();
 
         let command_buffer = builder.build().unwrap();
+        drop(record_span);
+        stats.record_time = record_started.elapsed();
+        let _span = info_span!("submit_present").entered();
         let future = self
             .previous_frame_end
             .take()
@@ -483,17 +784,129 @@ impl RendererSystem {
                 self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
             }
         }
+        stats
+    }
+}
+
+//Binding state for one frame's command buffer, so batches only bind what differs from the
+//previous batch. Each pipeline gets one frame descriptor set per frame, and one material
+//set for each material drawn with it.
+struct FrameBindings<'a> {
+    uniforms: FrameUniforms,
+    frame_sets: HashMap<&'a str, Arc<PersistentDescriptorSet>>,
+    materials: HashMap<MaterialHandle, MaterialResources>,
+    //None for pipelines that ignore materials
+    material_sets: HashMap<(&'a str, MaterialHandle), Option<Arc<PersistentDescriptorSet>>>,
+    instance_buffer: Option<Subbuffer<[InstanceData]>>,
+    instances_bound: bool,
+    //Key and pass of the pipeline variant bound
+    pipeline: Option<(&'a str, DrawPass)>,
+    material: Option<MaterialHandle>,
+    mesh: Option<MeshId>,
+    //Pipeline, descriptor set and buffer binds recorded so far
+    state_changes: u32,
+}
+
+impl<'a> FrameBindings<'a> {
+    fn new(
+        uniforms: FrameUniforms,
+        materials: HashMap<MaterialHandle, MaterialResources>,
+        instance_buffer: Option<Subbuffer<[InstanceData]>>,
+    ) -> Self {
+        FrameBindings {
+            uniforms,
+            frame_sets: HashMap::new(),
+            materials,
+            material_sets: HashMap::new(),
+            instance_buffer,
+            instances_bound: false,
+            pipeline: None,
+            material: None,
+            mesh: None,
+            state_changes: 0,
+        }
+    }
+
+    fn draw(
+        &mut self,
+        batch: &DrawBatch<'a>,
+        pipeline: &dyn RenderPipeline,
+        mesh: &GpuMesh,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<()> {
+        if !self.instances_bound {
+            let instance_buffer = self.instance_buffer.clone().context("No instance buffer this frame")?;
+            command_buffer.bind_vertex_buffers(INSTANCE_BINDING, instance_buffer)?;
+            self.instances_bound = true;
+            self.state_changes += 1;
+        }
+        if self.pipeline != Some((batch.pipeline_key, batch.pass)) {
+            let frame_set = match self.frame_sets.get(batch.pipeline_key) {
+                Some(frame_set) => frame_set.clone(),
+                None => {
+                    let frame_set = pipeline.frame_set(&self.uniforms, descriptor_set_allocator)?;
+                    self.frame_sets.insert(batch.pipeline_key, frame_set.clone());
+                    frame_set
+                }
+            };
+            pipeline.bind(batch.pass, frame_set, command_buffer)?;
+            self.pipeline = Some((batch.pipeline_key, batch.pass));
+            self.material = None;
+            //Pipelines can read different streams of the same mesh
+            self.mesh = None;
+            self.state_changes += 2;
+        }
+        if self.material != Some(batch.material) {
+            let key = (batch.pipeline_key, batch.material);
+            let material_set = match self.material_sets.get(&key) {
+                Some(material_set) => material_set.clone(),
+                None => {
+                    let material = self
+                        .materials
+                        .get(&batch.material)
+                        .context("Material wasn't prepared this frame")?;
+                    let material_set = pipeline.material_set(material, descriptor_set_allocator)?;
+                    self.material_sets.insert(key, material_set.clone());
+                    material_set
+                }
+            };
+            if let Some(material_set) = material_set {
+                pipeline.bind_material(material_set, command_buffer)?;
+                self.state_changes += 1;
+            }
+            self.material = Some(batch.material);
+        }
+        if self.mesh != Some(batch.mesh.id()) {
+            command_buffer
+                .bind_vertex_buffers(
+                    INSTANCE_BINDING + 1,
+                    mesh.vertex_buffers_for(pipeline.vertex_attributes())?,
+                )?
+                .bind_index_buffer(mesh.index_buffer.clone())?;
+            self.mesh = Some(batch.mesh.id());
+            self.state_changes += 2;
+        }
+        pipeline.render(mesh, batch.instances.clone(), command_buffer)
+    }
+}
+
+//Warns why an entity can't be drawn, unless that was already the last warning for it
+fn warn_incompatible(warned: &mut HashMap<usize, String>, entity: usize, reason: String) {
+    if warned.get(&entity) != Some(&reason) {
+        warn!("{reason}");
+        warned.insert(entity, reason);
     }
 }
 
 fn update_camera_perspective(current_scene: Arc<RwLock<Scene>>, aspect_ratio: f32) {
     //info!("Camera perspective");
-    let current_scene = current_scene.read().unwrap();
+    let current_scene = current_scene.read_or_recover();
     if let Some(binding) = current_scene.get_component_vec::<CameraComponent>() {
-        let mut cameras = binding.write().unwrap();
+        let mut cameras = binding.write_or_recover();
 
         if let Some(binding) = current_scene.get_component_vec::<TransformComponent>() {
-            let transforms = binding.read().unwrap();
+            let transforms = binding.read_or_recover();
             let zip = transforms.iter().zip(cameras.iter_mut());
             let iter =
                 zip.filter_map(|(transform, camera)| Some((transform.as_ref()?, camera.as_mut()?)));
@@ -510,14 +923,14 @@ fn update_camera_perspective(current_scene: Arc<RwLock<Scene>>, aspect_ratio: f3
 
 fn get_camera_view_and_projection(current_scene: Arc<RwLock<Scene>>) -> (Mat4, Mat4) {
     // info!("Camera view and proj");
-    let current_scene = current_scene.read().unwrap();
+    let current_scene = current_scene.read_or_recover();
     if let Some(binding) = current_scene.get_component_vec::<CameraComponent>() {
-        let cameras = binding.read().unwrap();
+        let cameras = binding.read_or_recover();
 
         let binding = current_scene
             .get_component_vec::<TransformComponent>()
             .unwrap();
-        let mut transforms = binding.write().unwrap();
+        let mut transforms = binding.write_or_recover();
 
         let zip = transforms.iter_mut().zip(cameras.iter());
         let iter =
@@ -535,4 +948,49 @@ fn get_camera_view_and_projection(current_scene: Arc<RwLock<Scene>>) -> (Mat4, M
     (Mat4::IDENTITY, Mat4::IDENTITY)
 }
 
+//Highest count the GPU can render colour and depth with that doesn't exceed the request
+fn supported_sample_count(device: &Device, requested: u32) -> SampleCount {
+    let properties = device.physical_device().properties();
+    let supported =
+        properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+    [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .find(|samples| u32::from(*samples) <= requested && supported.contains_enum(*samples))
+    .unwrap_or(SampleCount::Sample1)
+}
 
+fn material_resources<L>(
+    material: &Material,
+    uniform_buffer_allocator: &SubbufferAllocator,
+    texture_cache: &mut TextureCache,
+    command_buffer: &mut AutoCommandBufferBuilder<L>,
+) -> Result<MaterialResources> {
+    let uniform = uniform_buffer_allocator.allocate_sized::<MaterialData>()?;
+    *uniform.write()? = MaterialData {
+        base_color: material.base_color.to_array(),
+        emissive: material.emissive.to_array(),
+        roughness: material.roughness,
+        metallic: material.metallic,
+    };
+    Ok(MaterialResources {
+        uniform,
+        albedo: texture_cache.get_or_load(
+            material.albedo_texture.as_deref(),
+            TextureKind::Albedo,
+            command_buffer,
+        )?,
+        normal: texture_cache.get_or_load(
+            material.normal_texture.as_deref(),
+            TextureKind::Normal,
+            command_buffer,
+        )?,
+        sampler: texture_cache.sampler(material.sampler)?,
+    })
+}
@@ -0,0 +1,140 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::{
+    component::{
+        light_component::{DirectionalLight, PointLight, SpotLight},
+        transform_component::TransformComponent,
+    },
+    resource::ambient_light::AmbientLight,
+    scene::{lock::RecoverLock, Scene},
+    shaders::lit_fragment::{Light, LightData},
+};
+
+//Lights the lit shaders take per frame, has to match MAX_LIGHTS in shaders::lit_fragment
+pub const MAX_LIGHTS: usize = 16;
+
+const DIRECTIONAL: f32 = 0.0;
+const POINT: f32 = 1.0;
+const SPOT: f32 = 2.0;
+
+#[derive(Clone, Copy)]
+struct GatheredLight {
+    //Lower is kept first when there are more than MAX_LIGHTS
+    priority: f32,
+    position: Vec4,
+    direction: Vec4,
+    color: Vec4,
+    cone: Vec4,
+}
+
+impl GatheredLight {
+    fn to_light(self) -> Light {
+        Light {
+            position: self.position.to_array(),
+            direction: self.direction.to_array(),
+            color: self.color.to_array(),
+            cone: self.cone.to_array(),
+        }
+    }
+}
+
+//The frame's LightData uniform. Every directional light is kept, then the point and spot
+//lights nearest the camera up to MAX_LIGHTS. transforms is passed in as the renderer
+//already holds its lock.
+pub fn gather_lights(
+    scene: &Scene,
+    transforms: &[Option<TransformComponent>],
+    camera_position: Vec3,
+) -> LightData {
+    let transform_of = |entity: usize| {
+        transforms
+            .get(entity)
+            .and_then(Option::as_ref)
+            .map_or(Mat4::IDENTITY, |transform| transform.transform)
+    };
+    let mut gathered = vec![];
+
+    if let Some(lights) = scene.get_component_vec::<DirectionalLight>() {
+        for (entity, light) in lights.read_or_recover().iter().enumerate() {
+            let Some(light) = light else {
+                continue;
+            };
+            let direction = transform_of(entity).transform_vector3(light.direction);
+            gathered.push(GatheredLight {
+                priority: f32::NEG_INFINITY,
+                position: Vec4::new(0.0, 0.0, 0.0, DIRECTIONAL),
+                direction: direction.extend(0.0),
+                color: (light.color * light.intensity).extend(0.0),
+                cone: Vec4::ZERO,
+            });
+        }
+    }
+
+    if let Some(lights) = scene.get_component_vec::<PointLight>() {
+        for (entity, light) in lights.read_or_recover().iter().enumerate() {
+            let Some(light) = light else {
+                continue;
+            };
+            let position = transform_of(entity).transform_point3(Vec3::ZERO);
+            gathered.push(GatheredLight {
+                priority: position.distance(camera_position),
+                position: position.extend(POINT),
+                direction: Vec4::new(0.0, 0.0, 0.0, light.range),
+                color: (light.color * light.intensity).extend(0.0),
+                cone: Vec4::ZERO,
+            });
+        }
+    }
+
+    if let Some(lights) = scene.get_component_vec::<SpotLight>() {
+        for (entity, light) in lights.read_or_recover().iter().enumerate() {
+            let Some(light) = light else {
+                continue;
+            };
+            let transform = transform_of(entity);
+            let position = transform.transform_point3(Vec3::ZERO);
+            gathered.push(GatheredLight {
+                priority: position.distance(camera_position),
+                position: position.extend(SPOT),
+                direction: transform.transform_vector3(light.direction).extend(light.range),
+                color: (light.color * light.intensity).extend(light.outer_angle.cos()),
+                cone: Vec4::new(light.inner_angle.cos(), 0.0, 0.0, 0.0),
+            });
+        }
+    }
+
+    gathered.sort_by(|a, b| a.priority.total_cmp(&b.priority));
+    gathered.truncate(MAX_LIGHTS);
+
+    let ambient = scene
+        .get_resource::<AmbientLight>()
+        .map_or_else(AmbientLight::default, |ambient| *ambient.read_or_recover());
+    let unused = GatheredLight {
+        priority: 0.0,
+        position: Vec4::ZERO,
+        direction: Vec4::ZERO,
+        color: Vec4::ZERO,
+        cone: Vec4::ZERO,
+    };
+    LightData {
+        lights: std::array::from_fn(|index| gathered.get(index).copied().unwrap_or(unused).to_light()),
+        ambient: ambient.radiance().extend(1.0).to_array(),
+        camera_position: camera_position.to_array(),
+        light_count: gathered.len() as u32,
+    }
+}
@@ -0,0 +1,217 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, IndexBuffer, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+};
+
+use crate::{
+    component::mesh_filter_component::{MeshFilterComponent, MeshId},
+    geometry::mesh::{Indices, Mesh, VertexAttribute},
+};
+
+//Device local buffers for one mesh, a vertex buffer per attribute stream
+#[derive(Clone)]
+pub struct GpuMesh {
+    pub vertex_buffers: BTreeMap<VertexAttribute, Subbuffer<[u8]>>,
+    pub index_buffer: IndexBuffer,
+}
+
+impl GpuMesh {
+    pub fn index_count(&self) -> u32 {
+        self.index_buffer.len() as u32
+    }
+
+    //The streams for attributes in order, ready to bind one after another
+    pub fn vertex_buffers_for(
+        &self,
+        attributes: &[VertexAttribute],
+    ) -> Result<Vec<Subbuffer<[u8]>>> {
+        attributes
+            .iter()
+            .map(|attribute| {
+                self.vertex_buffers
+                    .get(attribute)
+                    .cloned()
+                    .with_context(|| format!("Mesh has no {attribute:?} stream"))
+            })
+            .collect()
+    }
+}
+
+struct CachedMesh {
+    mesh: GpuMesh,
+    last_used: u64,
+}
+
+//GPU copies of MeshFilterComponent meshes keyed by MeshId. A mesh is uploaded through a
+//staging buffer the first frame its id is drawn and dropped the first frame it isn't.
+pub struct MeshCache {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    meshes: HashMap<MeshId, CachedMesh>,
+    //Meshes that failed validation or upload and the frame they were last asked for. Only the
+    //first attempt reports the error, editing the mesh gives it a new id and another try.
+    //Pruned like meshes so ids left behind by edits don't pile up.
+    failed: HashMap<MeshId, u64>,
+    frame: u64,
+    uploads: u32,
+}
+
+impl MeshCache {
+    pub fn new(memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
+        MeshCache {
+            memory_allocator,
+            meshes: HashMap::new(),
+            failed: HashMap::new(),
+            frame: 0,
+            uploads: 0,
+        }
+    }
+
+    //Uploads are recorded into command_buffer, so this has to be called outside a render pass.
+    //None for meshes without any vertices or indices. A mesh that fails validation or upload
+    //gives its error once, then None until it's edited.
+    pub fn get_or_upload<L>(
+        &mut self,
+        mesh_filter: &MeshFilterComponent,
+        command_buffer: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<Option<GpuMesh>> {
+        if let Some(cached) = self.meshes.get_mut(&mesh_filter.id()) {
+            cached.last_used = self.frame;
+            return Ok(Some(cached.mesh.clone()));
+        }
+        let mesh = mesh_filter.mesh();
+        let empty = mesh.vertex_count() == 0 || mesh.indices.is_empty();
+        if empty {
+            return Ok(None);
+        }
+        if let Some(last_seen) = self.failed.get_mut(&mesh_filter.id()) {
+            *last_seen = self.frame;
+            return Ok(None);
+        }
+        let gpu_mesh = match self.upload_mesh(mesh, command_buffer) {
+            Ok(gpu_mesh) => gpu_mesh,
+            Err(err) => {
+                self.failed.insert(mesh_filter.id(), self.frame);
+                return Err(err);
+            }
+        };
+        self.uploads += 1;
+        self.meshes.insert(
+            mesh_filter.id(),
+            CachedMesh {
+                mesh: gpu_mesh.clone(),
+                last_used: self.frame,
+            },
+        );
+        Ok(Some(gpu_mesh))
+    }
+
+    fn upload_mesh<L>(
+        &self,
+        mesh: &Mesh,
+        command_buffer: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<GpuMesh> {
+        mesh.validate()?;
+        let mut vertex_buffers = BTreeMap::new();
+        for (attribute, stream) in mesh.attributes() {
+            let buffer = self.upload(
+                stream.as_bytes().iter().copied(),
+                BufferUsage::VERTEX_BUFFER,
+                command_buffer,
+            )?;
+            vertex_buffers.insert(attribute, buffer);
+        }
+        let index_buffer = match &mesh.indices {
+            Indices::U16(indices) => IndexBuffer::U16(self.upload(
+                indices.iter().copied(),
+                BufferUsage::INDEX_BUFFER,
+                command_buffer,
+            )?),
+            Indices::U32(indices) => IndexBuffer::U32(self.upload(
+                indices.iter().copied(),
+                BufferUsage::INDEX_BUFFER,
+                command_buffer,
+            )?),
+        };
+        Ok(GpuMesh {
+            vertex_buffers,
+            index_buffer,
+        })
+    }
+
+    //Evicts meshes nothing drew this frame, and forgets failures nothing asked for. Command
+    //buffers still in flight hold their own references to the buffers, so they stay alive
+    //until the GPU is done with them. Returns how many meshes were uploaded this frame.
+    pub fn end_frame(&mut self) -> u32 {
+        let frame = self.frame;
+        self.meshes.retain(|_, cached| cached.last_used == frame);
+        self.failed.retain(|_, last_seen| *last_seen == frame);
+        self.frame += 1;
+        std::mem::take(&mut self.uploads)
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+
+    fn upload<T, L>(
+        &self,
+        data: impl ExactSizeIterator<Item = T>,
+        usage: BufferUsage,
+        command_buffer: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<Subbuffer<[T]>>
+    where
+        T: BufferContents,
+    {
+        let staging = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            data,
+        )?;
+        let buffer = Buffer::new_slice::<T>(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: usage | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            staging.len(),
+        )?;
+        command_buffer.copy_buffer(CopyBufferInfo::buffers(staging, buffer.clone()))?;
+        Ok(buffer)
+    }
+}
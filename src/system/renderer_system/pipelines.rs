@@ -11,37 +11,310 @@
 //WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 //See the License for the specific language governing permissions and
 //limitations under the License.
+pub mod blinn_phong;
 pub mod lines;
 pub mod teapot;
 pub mod teapot_v2;
+pub mod textured;
 
+use anyhow::{Context, Result};
+use std::ops::Range;
 use std::sync::Arc;
 use vulkano::{
+    buffer::Subbuffer,
     command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
-    descriptor_set::allocator::StandardDescriptorSetAllocator,
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
     device::Device,
-    memory::allocator::StandardMemoryAllocator,
-    pipeline::graphics::vertex_input::Vertex as VulkanVertex,
-    render_pass::RenderPass,
+    image::{sampler::Sampler, view::ImageView, SampleCount},
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{DepthState, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::{CullMode, RasterizationState},
+            vertex_input::{Vertex, VertexDefinition, VertexInputState},
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    render_pass::{RenderPass, Subpass},
+    shader::EntryPoint,
+    ValidationError,
 };
 
-pub trait GraphicsPipelineWrapper {
-    type T: VulkanVertex;
-    type E;
-    fn name(&self) -> String;
+use crate::{
+    component::mesh_renderer_component::DrawPass,
+    geometry::{mesh::VertexAttribute, vertex::InstanceData},
+    shaders::{
+        lit_fragment::{LightData, MaterialData},
+        vertex::CameraData,
+    },
+};
+
+use super::mesh_cache::GpuMesh;
+
+//Uniforms written once per frame, shared by every pipeline
+pub struct FrameUniforms {
+    pub camera: Subbuffer<CameraData>,
+    pub lights: Subbuffer<LightData>,
+}
+
+//Everything set 1 can hold for one material. Missing textures are already replaced by
+//their fallbacks.
+pub struct MaterialResources {
+    pub uniform: Subbuffer<MaterialData>,
+    pub albedo: Arc<ImageView>,
+    pub normal: Arc<ImageView>,
+    pub sampler: Arc<Sampler>,
+}
+
+//A graphics pipeline meshes can be drawn with, registered on the renderer under the key
+//Material::pipeline_key refers to
+pub trait RenderPipeline {
+    fn name(&self) -> &str;
+    //Called whenever the swapchain is recreated, before any draws. A pipeline that fails
+    //is dropped from the renderer and its meshes are skipped like ones with unknown keys.
     fn create(
         &mut self,
-        //vertex_input: &Self::T,
         device: Arc<Device>,
         render_pass: Arc<RenderPass>,
         image_extent: [u32; 2],
-    );
+    ) -> Result<()>;
+    //None until create has been called
+    fn pipeline(&self) -> Option<&Arc<GraphicsPipeline>>;
+    //Alpha blended with depth writes off, for DrawPass::Transparent. Its layout has to match
+    //the opaque pipeline's. Pipelines without one skip transparent meshes.
+    fn transparent_pipeline(&self) -> Option<&Arc<GraphicsPipeline>> {
+        None
+    }
+    //The mesh streams the vertex shader reads, bound in this order from INSTANCE_BINDING + 1.
+    //Meshes missing any of them are skipped.
+    fn vertex_attributes(&self) -> &[VertexAttribute] {
+        &[
+            VertexAttribute::Position,
+            VertexAttribute::Color,
+            VertexAttribute::Normal,
+        ]
+    }
+
+    //Set 0, made once per frame for each pipeline that draws. By default only the camera
+    //at binding 0, pipelines using more of the frame's uniforms override this.
+    fn frame_set(
+        &self,
+        uniforms: &FrameUniforms,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    ) -> Result<Arc<PersistentDescriptorSet>> {
+        descriptor_set(
+            self,
+            FRAME_SET,
+            [WriteDescriptorSet::buffer(0, uniforms.camera.clone())],
+            descriptor_set_allocator,
+        )
+    }
+
+    //Set 1, one material's parameters. Made once per frame for each material drawn with
+    //the pipeline, None for pipelines that ignore materials.
+    fn material_set(
+        &self,
+        _material: &MaterialResources,
+        _descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    ) -> Result<Option<Arc<PersistentDescriptorSet>>> {
+        Ok(None)
+    }
+
+    //Every render after this draws with the pass's variant of the pipeline until another
+    //one is bound
+    fn bind(
+        &self,
+        pass: DrawPass,
+        frame_set: Arc<PersistentDescriptorSet>,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<()> {
+        let pipeline = match pass {
+            DrawPass::Opaque => self.pipeline(),
+            DrawPass::Transparent => self.transparent_pipeline(),
+        }
+        .with_context(|| format!("Pipeline has no {pass:?} variant"))?;
+        command_buffer
+            .bind_pipeline_graphics(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                FRAME_SET as u32,
+                frame_set,
+            )?;
+        Ok(())
+    }
+
+    //Expects bind to have been called, renders after this use the material until another
+    //one is bound
+    fn bind_material(
+        &self,
+        material_set: Arc<PersistentDescriptorSet>,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<()> {
+        let pipeline = self.pipeline().context("Pipeline used before creation")?;
+        command_buffer.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            MATERIAL_SET as u32,
+            material_set,
+        )?;
+        Ok(())
+    }
+
+    //Expects bind to have been called and the mesh's buffers to be bound, with the frame's
+    //instance buffer at INSTANCE_BINDING. Draws the mesh once per instance in the range.
     fn render(
         &self,
-        vertex_input: Vec<Self::T>,
-        extra: &Self::E,
-        memory_allocator: Arc<StandardMemoryAllocator>,
-        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        mesh: &GpuMesh,
+        instances: Range<u32>,
         command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    );
+    ) -> Result<()> {
+        command_buffer.draw_indexed(
+            mesh.index_count(),
+            instances.end - instances.start,
+            0,
+            0,
+            instances.start,
+        )?;
+        Ok(())
+    }
+}
+
+pub const FRAME_SET: usize = 0;
+pub const MATERIAL_SET: usize = 1;
+//Vertex buffer binding of the per instance data, the mesh's streams follow it
+pub const INSTANCE_BINDING: u32 = 0;
+
+//Instance data at INSTANCE_BINDING then one binding per attribute, matched to the vertex
+//shader's inputs by name
+pub fn vertex_input_state(
+    attributes: &[VertexAttribute],
+    vertex_shader: &EntryPoint,
+) -> Result<VertexInputState, Box<ValidationError>> {
+    std::iter::once(InstanceData::per_instance())
+        .chain(attributes.iter().map(|attribute| attribute.description()))
+        .collect::<Vec<_>>()
+        .definition(&vertex_shader.info().input_interface)
+}
+
+//How one variant of a pipeline writes to the colour and depth attachments
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineBlend {
+    pub blend: Option<AttachmentBlend>,
+    pub depth_write: bool,
+}
+
+impl PipelineBlend {
+    //Replaces whatever is behind and writes depth, for DrawPass::Opaque
+    pub fn opaque() -> Self {
+        PipelineBlend {
+            blend: None,
+            depth_write: true,
+        }
+    }
+
+    //Blends by the material's alpha and leaves depth alone, so transparent surfaces drawn
+    //later still show the ones behind them
+    pub fn transparent() -> Self {
+        PipelineBlend {
+            blend: Some(AttachmentBlend::alpha()),
+            depth_write: false,
+        }
+    }
+}
+
+//A back face culled triangle pipeline for the first subpass of render_pass, reading the
+//attributes from their streams. Variants built from the same shaders have matching layouts,
+//so descriptor sets made for one work with the others.
+pub fn build_graphics_pipeline(
+    device: Arc<Device>,
+    vs: &EntryPoint,
+    fs: &EntryPoint,
+    attributes: &[VertexAttribute],
+    render_pass: Arc<RenderPass>,
+    image_extent: [u32; 2],
+    blend: PipelineBlend,
+) -> Result<Arc<GraphicsPipeline>> {
+    let vertex_input_state = vertex_input_state(attributes, vs)
+        .context("Vertex shader inputs don't match the pipeline's vertex attributes")?;
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs.clone()),
+        PipelineShaderStageCreateInfo::new(fs.clone()),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass, 0).context("Render pass has no first subpass")?;
+
+    Ok(GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState {
+                viewports: [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: [image_extent[0] as f32, image_extent[1] as f32],
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            }),
+            rasterization_state: Some(RasterizationState::default().cull_mode(CullMode::Back)),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState {
+                    write_enable: blend.depth_write,
+                    ..DepthState::simple()
+                }),
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: blend.blend,
+                    ..Default::default()
+                },
+            )),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?)
+}
+
+//Builds descriptor set number set of pipeline's layout from writes
+pub fn descriptor_set<P: RenderPipeline + ?Sized>(
+    pipeline: &P,
+    set: usize,
+    writes: impl IntoIterator<Item = WriteDescriptorSet>,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+) -> Result<Arc<PersistentDescriptorSet>> {
+    let pipeline = pipeline.pipeline().context("Pipeline used before creation")?;
+    let layout = pipeline
+        .layout()
+        .set_layouts()
+        .get(set)
+        .with_context(|| format!("Pipeline has no descriptor set {set}"))?;
+    Ok(PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        layout.clone(),
+        writes,
+        [],
+    )?)
 }
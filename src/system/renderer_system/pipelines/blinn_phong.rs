@@ -0,0 +1,124 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::{
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    pipeline::GraphicsPipeline,
+    render_pass::RenderPass,
+    shader::EntryPoint,
+};
+
+use super::{
+    build_graphics_pipeline, descriptor_set, FrameUniforms, MaterialResources, PipelineBlend,
+    RenderPipeline, FRAME_SET, MATERIAL_SET,
+};
+
+//Blinn-Phong shading from the frame's lights and the material, normals from the instance
+//normal matrix
+pub struct BlinnPhong {
+    pub vs: EntryPoint,
+    pub fs: EntryPoint,
+    //Set on
+    pipeline: Option<Arc<GraphicsPipeline>>,
+    transparent_pipeline: Option<Arc<GraphicsPipeline>>,
+}
+impl BlinnPhong {
+    pub fn new(device: Arc<Device>) -> Result<Self> {
+        let vs = crate::shaders::lit_vertex::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .context("Could not create vertex shader")?;
+
+        let fs: vulkano::shader::EntryPoint = crate::shaders::lit_fragment::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .context("Could not create frag shader")?;
+
+        Ok(Self {
+            vs,
+            fs,
+            pipeline: None,
+            transparent_pipeline: None,
+        })
+    }
+}
+
+impl RenderPipeline for BlinnPhong {
+    fn pipeline(&self) -> Option<&Arc<GraphicsPipeline>> {
+        self.pipeline.as_ref()
+    }
+    fn transparent_pipeline(&self) -> Option<&Arc<GraphicsPipeline>> {
+        self.transparent_pipeline.as_ref()
+    }
+    fn create(
+        &mut self,
+        device: Arc<Device>,
+        render_pass: Arc<RenderPass>,
+        image_extent: [u32; 2],
+    ) -> Result<()> {
+        let attributes = self.vertex_attributes();
+        let build = |blend| {
+            build_graphics_pipeline(
+                device.clone(),
+                &self.vs,
+                &self.fs,
+                attributes,
+                render_pass.clone(),
+                image_extent,
+                blend,
+            )
+        };
+        let pipeline = build(PipelineBlend::opaque())?;
+        let transparent_pipeline = build(PipelineBlend::transparent())?;
+        self.pipeline = Some(pipeline);
+        self.transparent_pipeline = Some(transparent_pipeline);
+        Ok(())
+    }
+    fn name(&self) -> &str {
+        "lit"
+    }
+    //Camera at binding 0, lights at binding 1
+    fn frame_set(
+        &self,
+        uniforms: &FrameUniforms,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    ) -> Result<Arc<PersistentDescriptorSet>> {
+        descriptor_set(
+            self,
+            FRAME_SET,
+            [
+                WriteDescriptorSet::buffer(0, uniforms.camera.clone()),
+                WriteDescriptorSet::buffer(1, uniforms.lights.clone()),
+            ],
+            descriptor_set_allocator,
+        )
+    }
+    fn material_set(
+        &self,
+        material: &MaterialResources,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    ) -> Result<Option<Arc<PersistentDescriptorSet>>> {
+        Ok(Some(descriptor_set(
+            self,
+            MATERIAL_SET,
+            [WriteDescriptorSet::buffer(0, material.uniform.clone())],
+            descriptor_set_allocator,
+        )?))
+    }
+}
@@ -14,21 +14,10 @@
 
 
 use anyhow::{Context, Result};
-use glam::Mat4;
 use std::sync::Arc;
 use vulkano::{
-    buffer::{
-        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
-        Buffer, BufferCreateInfo, BufferUsage,
-    },
-    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
-    descriptor_set::{
-        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
-    },
     device::{Device},
-    memory::allocator::{
-        AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator,
-    },
+    image::SampleCount,
     pipeline::{
         graphics::{
             color_blend::{ColorBlendAttachmentState, ColorBlendState},
@@ -36,29 +25,18 @@ use vulkano::{
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
             rasterization::{PolygonMode, RasterizationState},
-            vertex_input::{Vertex as VulkanVertex, VertexDefinition},
             viewport::{Viewport, ViewportState},
             GraphicsPipelineCreateInfo,
         },
         layout::PipelineDescriptorSetLayoutCreateInfo,
-        GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        GraphicsPipeline, PipelineLayout,
         PipelineShaderStageCreateInfo,
     },
     render_pass::{RenderPass, Subpass},
     shader::EntryPoint,
 };
 
-use crate::{geometry::vertex::PositionColorNormal, shaders::vertex};
-
-use super::GraphicsPipelineWrapper;
-
-#[derive(Clone, Debug)]
-pub struct LinesExtra {
-    pub indices: Vec<u32>,
-    pub model: Mat4,
-    pub view: Mat4,
-    pub proj: Mat4,
-}
+use super::{vertex_input_state, RenderPipeline};
 
 pub struct Lines {
     pub vs: EntryPoint,
@@ -86,129 +64,20 @@ impl Lines {
     }
 }
 
-impl GraphicsPipelineWrapper for Lines {
-    type T = PositionColorNormal;
-    type E = LinesExtra;
-
-    fn render(
-        &self,
-        vertex_input: Vec<Self::T>,
-        extra: &Self::E,
-        memory_allocator: Arc<StandardMemoryAllocator>,
-        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
-        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) {
-        if let Some(pipeline) = self.pipeline.clone() {
-            //let aspect_ratio = extent[0] as f32 / extent[1] as f32;
-            //info!("Input: {:?}", input);
-            //info!("Extra: {:?}", extra);
-            let vertex_buffer = Buffer::from_iter(
-                memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::VERTEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                vertex_input.clone(),
-            )
-            .unwrap();
-            /*let normals_buffer = Buffer::from_iter(
-                memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::VERTEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                input.normals.clone(),
-            )
-            .unwrap();
-            */
-            let index_buffer = Buffer::from_iter(
-                memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::INDEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                extra.indices.clone(),
-            )
-            .unwrap();
-
-            let uniform_buffer = SubbufferAllocator::new(
-                memory_allocator,
-                SubbufferAllocatorCreateInfo {
-                    buffer_usage: BufferUsage::UNIFORM_BUFFER,
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-            );
-
-            let uniform_buffer_subbuffer = {
-                let uniform_data = vertex::VPData {
-                    model: extra.model.to_cols_array_2d(),
-                    view: extra.view.to_cols_array_2d(),
-                    projection: extra.proj.to_cols_array_2d(),
-                };
-
-                let subbuffer = uniform_buffer.allocate_sized().unwrap();
-                *subbuffer.write().unwrap() = uniform_data;
-
-                subbuffer
-            };
-
-            let layout = pipeline.layout().set_layouts().get(0).unwrap();
-            let set = PersistentDescriptorSet::new(
-                &descriptor_set_allocator,
-                layout.clone(),
-                [WriteDescriptorSet::buffer(0, uniform_buffer_subbuffer)],
-                [],
-            )
-            .unwrap();
-
-            command_buffer
-                .bind_pipeline_graphics(pipeline.clone())
-                .unwrap()
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    pipeline.layout().clone(),
-                    0,
-                    set,
-                )
-                .unwrap()
-                .bind_vertex_buffers(0, vertex_buffer.clone())
-                .unwrap()
-                .bind_index_buffer(index_buffer.clone())
-                .unwrap()
-                .draw_indexed(index_buffer.len() as u32, 1, 0, 0, 0)
-                .unwrap();
-        } else {
-            panic!("Rendering a pipeline before creation");
-        }
+impl RenderPipeline for Lines {
+    fn pipeline(&self) -> Option<&Arc<GraphicsPipeline>> {
+        self.pipeline.as_ref()
     }
     fn create(
         &mut self,
-        //input: &Self::T,
         device: Arc<Device>,
         render_pass: Arc<RenderPass>,
         image_extent: [u32; 2],
-    ) {
+    ) -> Result<()> {
         let pipeline = {
-            let vertex_input_state = [Self::T::per_vertex()]
-                .definition(&self.vs.info().input_interface)
-                .unwrap();
+            let vertex_input_state =
+                vertex_input_state(self.vertex_attributes(), &self.vs)
+                    .context("Vertex shader inputs don't match the pipeline's vertex attributes")?;
             let stages = [
                 PipelineShaderStageCreateInfo::new(self.vs.clone()),
                 PipelineShaderStageCreateInfo::new(self.fs.clone()),
@@ -216,11 +85,9 @@ impl GraphicsPipelineWrapper for Lines {
             let layout = PipelineLayout::new(
                 device.clone(),
                 PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                    .into_pipeline_layout_create_info(device.clone())
-                    .unwrap(),
-            )
-            .unwrap();
-            let subpass = Subpass::from(render_pass, 0).unwrap();
+                    .into_pipeline_layout_create_info(device.clone())?,
+            )?;
+            let subpass = Subpass::from(render_pass, 0).context("Render pass has no first subpass")?;
 
             let mut rasterization_state = RasterizationState::default();
             rasterization_state.polygon_mode = PolygonMode::Line;
@@ -253,7 +120,10 @@ impl GraphicsPipelineWrapper for Lines {
                         depth: Some(DepthState::simple()),
                         ..Default::default()
                     }),
-                    multisample_state: Some(MultisampleState::default()),
+                    multisample_state: Some(MultisampleState {
+                        rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                        ..Default::default()
+                    }),
                     color_blend_state: Some(ColorBlendState::with_attachment_states(
                         subpass.num_color_attachments(),
                         ColorBlendAttachmentState::default(),
@@ -261,12 +131,12 @@ impl GraphicsPipelineWrapper for Lines {
                     subpass: Some(subpass.into()),
                     ..GraphicsPipelineCreateInfo::layout(layout)
                 },
-            )
-            .unwrap()
+            )?
         };
         self.pipeline = Some(pipeline);
+        Ok(())
     }
-    fn name(&self) -> String {
-        todo!()
+    fn name(&self) -> &str {
+        "lines"
     }
 }
@@ -14,59 +14,23 @@
 
 
 use anyhow::{Context, Result};
-use glam::Mat4;
 use std::sync::Arc;
 use vulkano::{
-    buffer::{
-        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
-        Buffer, BufferCreateInfo, BufferUsage,
-    },
-    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
-    descriptor_set::{
-        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
-    },
     device::{Device},
-    memory::allocator::{
-        AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator,
-    },
-    pipeline::{
-        graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{DepthState, DepthStencilState},
-            input_assembly::InputAssemblyState,
-            multisample::MultisampleState,
-            rasterization::{CullMode, RasterizationState},
-            vertex_input::{Vertex as VulkanVertex, VertexDefinition},
-            viewport::{Viewport, ViewportState},
-            GraphicsPipelineCreateInfo,
-        },
-        layout::PipelineDescriptorSetLayoutCreateInfo,
-        GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
-        PipelineShaderStageCreateInfo,
-    },
-    render_pass::{RenderPass, Subpass},
+    pipeline::GraphicsPipeline,
+    render_pass::RenderPass,
     shader::EntryPoint,
 };
 
-use crate::{geometry::vertex::PositionColorNormal, shaders::vertex};
+use super::{build_graphics_pipeline, PipelineBlend, RenderPipeline};
 
-use super::GraphicsPipelineWrapper;
-
-#[derive(Clone, Debug)]
-pub struct TeapotExtra {
-    pub indices: Vec<u32>,
-    pub model: Mat4,
-    pub view: Mat4,
-    pub proj: Mat4,
-}
-
-pub struct Teapot_v2 {
+pub struct TeapotV2 {
     pub vs: EntryPoint,
     pub fs: EntryPoint,
     //Set on
     pipeline: Option<Arc<GraphicsPipeline>>,
 }
-impl Teapot_v2 {
+impl TeapotV2 {
     pub fn new(device: Arc<Device>) -> Result<Self> {
         let vs = crate::shaders::vertex::load(device.clone())
             .unwrap()
@@ -86,180 +50,29 @@ impl Teapot_v2 {
     }
 }
 
-impl GraphicsPipelineWrapper for Teapot_v2 {
-    type T = PositionColorNormal;
-    type E = TeapotExtra;
-
-    fn render(
-        &self,
-        vertex_input: Vec<Self::T>,
-        extra: &Self::E,
-        memory_allocator: Arc<StandardMemoryAllocator>,
-        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
-        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) {
-        if let Some(pipeline) = &self.pipeline {
-            //let aspect_ratio = extent[0] as f32 / extent[1] as f32;
-            //info!("Input: {:?}", input);
-            //info!("Extra: {:?}", extra);
-            let vertex_buffer = Buffer::from_iter(
-                memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::VERTEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                vertex_input.clone(),
-            )
-            .unwrap();
-            /*let normals_buffer = Buffer::from_iter(
-                memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::VERTEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                input.normals.clone(),
-            )
-            .unwrap();
-            */
-            let index_buffer = Buffer::from_iter(
-                memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::INDEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                extra.indices.clone(),
-            )
-            .unwrap();
-
-            let uniform_buffer = SubbufferAllocator::new(
-                memory_allocator,
-                SubbufferAllocatorCreateInfo {
-                    buffer_usage: BufferUsage::UNIFORM_BUFFER,
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-            );
-
-            let uniform_buffer_subbuffer = {
-                let uniform_data = vertex::VPData {
-                    model: extra.model.to_cols_array_2d(),
-                    view: extra.view.to_cols_array_2d(),
-                    projection: extra.proj.to_cols_array_2d(),
-                };
-
-                let subbuffer = uniform_buffer.allocate_sized().unwrap();
-                *subbuffer.write().unwrap() = uniform_data;
-
-                subbuffer
-            };
-
-            let layout = pipeline.layout().set_layouts().get(0).unwrap();
-            let set = PersistentDescriptorSet::new(
-                &descriptor_set_allocator,
-                layout.clone(),
-                [WriteDescriptorSet::buffer(0, uniform_buffer_subbuffer)],
-                [],
-            )
-            .unwrap();
-
-            command_buffer
-                .bind_pipeline_graphics(pipeline.clone())
-                .unwrap()
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    pipeline.layout().clone(),
-                    0,
-                    set,
-                )
-                .unwrap()
-                .bind_vertex_buffers(0, vertex_buffer.clone())
-                .unwrap()
-                .bind_index_buffer(index_buffer.clone())
-                .unwrap()
-                .draw_indexed(index_buffer.len() as u32, 1, 0, 0, 0)
-                .unwrap();
-        } else {
-            panic!("Rendering a pipeline before creation");
-        }
+impl RenderPipeline for TeapotV2 {
+    fn pipeline(&self) -> Option<&Arc<GraphicsPipeline>> {
+        self.pipeline.as_ref()
     }
     fn create(
         &mut self,
-        //input: &Self::T,
         device: Arc<Device>,
         render_pass: Arc<RenderPass>,
         image_extent: [u32; 2],
-    ) {
-        let pipeline = {
-            let vertex_input_state = [Self::T::per_vertex()]
-                .definition(&self.vs.info().input_interface)
-                .unwrap();
-            let stages = [
-                PipelineShaderStageCreateInfo::new(self.vs.clone()),
-                PipelineShaderStageCreateInfo::new(self.fs.clone()),
-            ];
-            let layout = PipelineLayout::new(
-                device.clone(),
-                PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                    .into_pipeline_layout_create_info(device.clone())
-                    .unwrap(),
-            )
-            .unwrap();
-            let subpass = Subpass::from(render_pass, 0).unwrap();
-            let rasterization_state = RasterizationState::default().cull_mode(CullMode::Back);
-            //info!("Front face: {:?}", rasterization_state.front_face);
-
-            GraphicsPipeline::new(
-                device,
-                None,
-                GraphicsPipelineCreateInfo {
-                    stages: stages.into_iter().collect(),
-                    vertex_input_state: Some(vertex_input_state),
-                    input_assembly_state: Some(InputAssemblyState::default()),
-                    viewport_state: Some(ViewportState {
-                        viewports: [Viewport {
-                            offset: [0.0, 0.0],
-                            extent: [image_extent[0] as f32, image_extent[1] as f32],
-                            depth_range: 0.0..=1.0,
-                        }]
-                        .into_iter()
-                        .collect(),
-                        ..Default::default()
-                    }),
-                    rasterization_state: Some(rasterization_state),
-                    depth_stencil_state: Some(DepthStencilState {
-                        depth: Some(DepthState::simple()),
-                        ..Default::default()
-                    }),
-                    multisample_state: Some(MultisampleState::default()),
-                    color_blend_state: Some(ColorBlendState::with_attachment_states(
-                        subpass.num_color_attachments(),
-                        ColorBlendAttachmentState::default(),
-                    )),
-                    subpass: Some(subpass.into()),
-                    ..GraphicsPipelineCreateInfo::layout(layout)
-                },
-            )
-            .unwrap()
-        };
+    ) -> Result<()> {
+        let pipeline = build_graphics_pipeline(
+            device,
+            &self.vs,
+            &self.fs,
+            self.vertex_attributes(),
+            render_pass,
+            image_extent,
+            PipelineBlend::opaque(),
+        )?;
         self.pipeline = Some(pipeline);
+        Ok(())
     }
-    fn name(&self) -> String {
-        todo!()
+    fn name(&self) -> &str {
+        "teapot_v2"
     }
 }
@@ -0,0 +1,150 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::ops::Range;
+
+use crate::{
+    component::{
+        mesh_filter_component::MeshFilterComponent,
+        mesh_renderer_component::DrawPass,
+    },
+    geometry::vertex::InstanceData,
+    resource::materials::MaterialHandle,
+};
+
+struct RenderItem<'a> {
+    pass: DrawPass,
+    pipeline_key: &'a str,
+    material: MaterialHandle,
+    mesh: &'a MeshFilterComponent,
+    //View space distance along the camera's forward axis
+    depth: f32,
+    //Into RenderQueue::instances, which stays in the order items were pushed
+    instances: Range<usize>,
+}
+
+impl RenderItem<'_> {
+    fn same_batch(&self, other: &RenderItem) -> bool {
+        self.pass == other.pass
+            && self.pipeline_key == other.pipeline_key
+            && self.material == other.material
+            && self.mesh.id() == other.mesh.id()
+    }
+}
+
+//Consecutive queue items sharing a pass, pipeline, material and mesh, drawn as one
+//instanced draw
+pub struct DrawBatch<'a> {
+    pub pass: DrawPass,
+    pub pipeline_key: &'a str,
+    pub material: MaterialHandle,
+    pub mesh: &'a MeshFilterComponent,
+    //Into the buffer written by RenderQueue::write_instances
+    pub instances: Range<u32>,
+}
+
+//Everything drawn in a frame. Sorted so state changes are rare and opaque objects fill the
+//depth buffer front to back, then cut into batches.
+#[derive(Default)]
+pub struct RenderQueue<'a> {
+    items: Vec<RenderItem<'a>>,
+    instances: Vec<InstanceData>,
+}
+
+impl<'a> RenderQueue<'a> {
+    pub fn new() -> Self {
+        RenderQueue::default()
+    }
+
+    pub fn push(
+        &mut self,
+        pass: DrawPass,
+        pipeline_key: &'a str,
+        material: MaterialHandle,
+        mesh: &'a MeshFilterComponent,
+        depth: f32,
+        instances: impl IntoIterator<Item = InstanceData>,
+    ) {
+        let start = self.instances.len();
+        self.instances.extend(instances);
+        if self.instances.len() == start {
+            return;
+        }
+        self.items.push(RenderItem {
+            pass,
+            pipeline_key,
+            material,
+            mesh,
+            depth,
+            instances: start..self.instances.len(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    //Opaque by pipeline, material, mesh, then nearest first. Transparent farthest first, whatever the
+    //pipeline, since blending needs them in order.
+    pub fn sort(&mut self) {
+        self.items.sort_by(|a, b| {
+            a.pass.cmp(&b.pass).then_with(|| match a.pass {
+                DrawPass::Opaque => a
+                    .pipeline_key
+                    .cmp(b.pipeline_key)
+                    .then_with(|| a.material.cmp(&b.material))
+                    .then_with(|| a.mesh.id().cmp(&b.mesh.id()))
+                    .then_with(|| a.depth.total_cmp(&b.depth)),
+                DrawPass::Transparent => b.depth.total_cmp(&a.depth),
+            })
+        });
+    }
+
+    pub fn batches(&self) -> impl Iterator<Item = DrawBatch<'a>> + '_ {
+        let mut items = self.items.iter().peekable();
+        let mut first_instance = 0;
+        std::iter::from_fn(move || {
+            let first = items.next()?;
+            let mut count = first.instances.len();
+            while let Some(item) = items.next_if(|item| item.same_batch(first)) {
+                count += item.instances.len();
+            }
+            let instances = first_instance as u32..(first_instance + count) as u32;
+            first_instance += count;
+            Some(DrawBatch {
+                pass: first.pass,
+                pipeline_key: first.pipeline_key,
+                material: first.material,
+                mesh: first.mesh,
+                instances,
+            })
+        })
+    }
+
+    //Instances in sorted order, so each batch's range is contiguous. out has to hold
+    //instance_count of them.
+    pub fn write_instances(&self, out: &mut [InstanceData]) {
+        let mut written = 0;
+        for item in &self.items {
+            let instances = &self.instances[item.instances.clone()];
+            out[written..written + instances.len()].copy_from_slice(instances);
+            written += instances.len();
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::warn;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        AutoCommandBufferBuilder, BlitImageInfo, BufferImageCopy, CopyBufferToImageInfo, ImageBlit,
+    },
+    device::Device,
+    format::Format,
+    image::{
+        max_mip_levels, mip_level_extent,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+};
+
+use crate::resource::materials::{SamplerSettings, TextureFilter, TextureWrap};
+
+//KTX2 formats that can be uploaded as they are, anything else has to be converted first
+const KTX2_FORMATS: [(ktx2::Format, Format); 10] = [
+    (ktx2::Format::R8G8B8A8_UNORM, Format::R8G8B8A8_UNORM),
+    (ktx2::Format::R8G8B8A8_SRGB, Format::R8G8B8A8_SRGB),
+    (ktx2::Format::BC1_RGBA_UNORM_BLOCK, Format::BC1_RGBA_UNORM_BLOCK),
+    (ktx2::Format::BC1_RGBA_SRGB_BLOCK, Format::BC1_RGBA_SRGB_BLOCK),
+    (ktx2::Format::BC3_UNORM_BLOCK, Format::BC3_UNORM_BLOCK),
+    (ktx2::Format::BC3_SRGB_BLOCK, Format::BC3_SRGB_BLOCK),
+    (ktx2::Format::BC5_UNORM_BLOCK, Format::BC5_UNORM_BLOCK),
+    (ktx2::Format::BC5_SNORM_BLOCK, Format::BC5_SNORM_BLOCK),
+    (ktx2::Format::BC7_UNORM_BLOCK, Format::BC7_UNORM_BLOCK),
+    (ktx2::Format::BC7_SRGB_BLOCK, Format::BC7_SRGB_BLOCK),
+];
+
+//What a texture holds, which decides the colour space PNGs and JPEGs are read in and what
+//stands in for it when it's missing. KTX2 files carry their own format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    //sRGB colour, white when missing
+    Albedo,
+    //Linear tangent space normals, flat when missing
+    Normal,
+}
+
+impl TextureKind {
+    fn format(self) -> Format {
+        match self {
+            TextureKind::Albedo => Format::R8G8B8A8_SRGB,
+            TextureKind::Normal => Format::R8G8B8A8_UNORM,
+        }
+    }
+
+    fn fallback_texel(self) -> [u8; 4] {
+        match self {
+            TextureKind::Albedo => [255, 255, 255, 255],
+            TextureKind::Normal => [128, 128, 255, 255],
+        }
+    }
+}
+
+//Texels ready to copy into an image
+struct DecodedTexture {
+    format: Format,
+    extent: [u32; 2],
+    //Every mip level the file came with, largest first and packed one after another
+    data: Vec<u8>,
+    //Where each level starts in data. With one level the rest are generated on upload.
+    level_offsets: Vec<u64>,
+}
+
+//Sampled images loaded from disk, keyed by path and kind, and the samplers they're read
+//with. Textures stay loaded for the rest of the run.
+pub struct TextureCache {
+    device: Arc<Device>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    textures: HashMap<(PathBuf, TextureKind), Arc<ImageView>>,
+    //Warned about once, then drawn with the fallback
+    failed: HashSet<(PathBuf, TextureKind)>,
+    fallbacks: HashMap<TextureKind, Arc<ImageView>>,
+    samplers: Vec<(SamplerSettings, Arc<Sampler>)>,
+}
+
+impl TextureCache {
+    pub fn new(device: Arc<Device>, memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
+        TextureCache {
+            device,
+            memory_allocator,
+            textures: HashMap::new(),
+            failed: HashSet::new(),
+            fallbacks: HashMap::new(),
+            samplers: vec![],
+        }
+    }
+
+    //Decodes and uploads a texture the first time it's asked for. Uploads are recorded into
+    //command_buffer, so this has to be called outside a render pass. No path, or one that
+    //fails to load, gives the kind's fallback.
+    pub fn get_or_load<L>(
+        &mut self,
+        path: Option<&Path>,
+        kind: TextureKind,
+        command_buffer: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<Arc<ImageView>> {
+        let Some(path) = path else {
+            return self.fallback(kind, command_buffer);
+        };
+        let key = (path.to_path_buf(), kind);
+        if let Some(view) = self.textures.get(&key) {
+            return Ok(view.clone());
+        }
+        if !self.failed.contains(&key) {
+            match decode(path, kind).and_then(|texture| self.upload(texture, command_buffer)) {
+                Ok(view) => {
+                    self.textures.insert(key, view.clone());
+                    return Ok(view);
+                }
+                Err(err) => {
+                    warn!("Failed to load texture {}: {err:#}", path.display());
+                    self.failed.insert(key);
+                }
+            }
+        }
+        self.fallback(kind, command_buffer)
+    }
+
+    pub fn sampler(&mut self, settings: SamplerSettings) -> Result<Arc<Sampler>> {
+        if let Some((_, sampler)) = self.samplers.iter().find(|(cached, _)| *cached == settings) {
+            return Ok(sampler.clone());
+        }
+        let filter = match settings.filter {
+            TextureFilter::Nearest => Filter::Nearest,
+            TextureFilter::Linear => Filter::Linear,
+        };
+        let address_mode = match settings.wrap {
+            TextureWrap::Repeat => SamplerAddressMode::Repeat,
+            TextureWrap::MirroredRepeat => SamplerAddressMode::MirroredRepeat,
+            TextureWrap::ClampToEdge => SamplerAddressMode::ClampToEdge,
+        };
+        let max_anisotropy = self.device.physical_device().properties().max_sampler_anisotropy;
+        let anisotropy = settings
+            .anisotropy
+            .filter(|_| self.device.enabled_features().sampler_anisotropy)
+            .map(|anisotropy| anisotropy.clamp(1.0, max_anisotropy));
+        let sampler = Sampler::new(
+            self.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_mode: match filter {
+                    Filter::Nearest => SamplerMipmapMode::Nearest,
+                    _ => SamplerMipmapMode::Linear,
+                },
+                address_mode: [address_mode; 3],
+                anisotropy,
+                lod: if settings.mipmaps {
+                    SamplerCreateInfo::default().lod
+                } else {
+                    0.0..=0.0
+                },
+                ..Default::default()
+            },
+        )?;
+        self.samplers.push((settings, sampler.clone()));
+        Ok(sampler)
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    //A 1x1 texture of the kind's fallback texel
+    fn fallback<L>(
+        &mut self,
+        kind: TextureKind,
+        command_buffer: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<Arc<ImageView>> {
+        if let Some(view) = self.fallbacks.get(&kind) {
+            return Ok(view.clone());
+        }
+        let texture = DecodedTexture {
+            format: kind.format(),
+            extent: [1, 1],
+            data: kind.fallback_texel().to_vec(),
+            level_offsets: vec![0],
+        };
+        let view = self.upload(texture, command_buffer)?;
+        self.fallbacks.insert(kind, view.clone());
+        Ok(view)
+    }
+
+    fn upload<L>(
+        &self,
+        texture: DecodedTexture,
+        command_buffer: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<Arc<ImageView>> {
+        let extent = [texture.extent[0], texture.extent[1], 1];
+        //Compressed formats can't be blitted, they only get the levels they came with
+        let generate_mips =
+            texture.level_offsets.len() == 1 && texture.format.compression().is_none();
+        let mip_levels = if generate_mips {
+            max_mip_levels(extent)
+        } else {
+            texture.level_offsets.len() as u32
+        };
+        let image = Image::new(
+            self.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: texture.format,
+                extent,
+                mip_levels,
+                usage: ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+        )?;
+        let staging = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            texture.data,
+        )?;
+        let level_layers = |level: u32| ImageSubresourceLayers {
+            mip_level: level,
+            ..image.subresource_layers()
+        };
+        let level_extent = |level: u32| mip_level_extent(extent, level).unwrap_or([1, 1, 1]);
+
+        let regions = texture
+            .level_offsets
+            .iter()
+            .enumerate()
+            .map(|(level, offset)| BufferImageCopy {
+                buffer_offset: *offset,
+                image_subresource: level_layers(level as u32),
+                image_extent: level_extent(level as u32),
+                ..Default::default()
+            })
+            .collect();
+        command_buffer.copy_buffer_to_image(CopyBufferToImageInfo {
+            regions,
+            ..CopyBufferToImageInfo::buffer_image(staging, image.clone())
+        })?;
+
+        //Each level is a linear downscale of the one before
+        if generate_mips {
+            for level in 1..mip_levels {
+                command_buffer.blit_image(BlitImageInfo {
+                    regions: [ImageBlit {
+                        src_subresource: level_layers(level - 1),
+                        src_offsets: [[0, 0, 0], level_extent(level - 1)],
+                        dst_subresource: level_layers(level),
+                        dst_offsets: [[0, 0, 0], level_extent(level)],
+                        ..Default::default()
+                    }]
+                    .into(),
+                    filter: Filter::Linear,
+                    ..BlitImageInfo::images(image.clone(), image.clone())
+                })?;
+            }
+        }
+        Ok(ImageView::new_default(image)?)
+    }
+}
+
+fn decode(path: &Path, kind: TextureKind) -> Result<DecodedTexture> {
+    let bytes = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let is_ktx2 = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ktx2"));
+    if is_ktx2 {
+        decode_ktx2(&bytes)
+    } else {
+        decode_image(&bytes, kind)
+    }
+}
+
+//PNG or JPEG, expanded to RGBA
+fn decode_image(bytes: &[u8], kind: TextureKind) -> Result<DecodedTexture> {
+    let image = image::load_from_memory(bytes)?.into_rgba8();
+    Ok(DecodedTexture {
+        format: kind.format(),
+        extent: [image.width(), image.height()],
+        data: image.into_raw(),
+        level_offsets: vec![0],
+    })
+}
+
+//2D textures in one of KTX2_FORMATS, without supercompression
+fn decode_ktx2(bytes: &[u8]) -> Result<DecodedTexture> {
+    let reader = ktx2::Reader::new(bytes).map_err(|err| anyhow!("Invalid KTX2 file: {err:?}"))?;
+    let header = reader.header();
+    if header.supercompression_scheme.is_some() {
+        bail!("Supercompressed KTX2 files aren't supported");
+    }
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        bail!("Only 2D KTX2 textures are supported");
+    }
+    let ktx2_format = header.format.context("KTX2 file has no Vulkan format")?;
+    let format = KTX2_FORMATS
+        .iter()
+        .find(|(candidate, _)| *candidate == ktx2_format)
+        .map(|(_, format)| *format)
+        .with_context(|| format!("Unsupported KTX2 format {ktx2_format:?}"))?;
+
+    let mut data = vec![];
+    let mut level_offsets = vec![];
+    for level in reader.levels() {
+        level_offsets.push(data.len() as u64);
+        data.extend_from_slice(level.data);
+    }
+    if data.is_empty() {
+        bail!("KTX2 file has no image data");
+    }
+    Ok(DecodedTexture {
+        format,
+        extent: [header.pixel_width, header.pixel_height.max(1)],
+        data,
+        level_offsets,
+    })
+}
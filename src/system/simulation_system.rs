@@ -0,0 +1,76 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tracing::{error, info_span};
+
+use crate::{
+    resource::{input_state::InputState, time::Time},
+    scene::{lock::RecoverLock, Scene},
+    system::{controller_system::ControllerSystem, System},
+};
+
+//Everything that advances a scene by one frame without touching a window or the GPU,
+//shared by App and HeadlessRunner so both simulate the same way
+pub struct SimulationSystem {
+    pub controller_system: ControllerSystem,
+    systems: Vec<Box<dyn System>>,
+}
+
+impl SimulationSystem {
+    pub fn new() -> Self {
+        Self {
+            controller_system: ControllerSystem::new(),
+            systems: vec![],
+        }
+    }
+
+    //Systems run in the order they were added
+    pub fn add_system(&mut self, system: Box<dyn System>) {
+        self.systems.push(system);
+    }
+
+    pub fn step(&mut self, scene: &Arc<RwLock<Scene>>, delta: Duration) {
+        let _span = info_span!("simulation_step").entered();
+        let (time, input_state) = {
+            let mut scene = scene.write_or_recover();
+            (
+                scene.get_or_insert_resource::<Time>(),
+                scene.get_resource::<InputState>(),
+            )
+        };
+        time.write_or_recover().advance(delta);
+        if let Some(input_state) = input_state.as_ref() {
+            let _span = info_span!("input_begin_frame").entered();
+            let mut input_state = input_state.write_or_recover();
+            input_state.begin_frame();
+            if input_state.just_pressed("toggle_cursor_grab") {
+                input_state.toggle_cursor_grab();
+            }
+        }
+        self.controller_system.run(scene.clone());
+        for system in self.systems.iter_mut() {
+            let _span = info_span!("system", name = system.name()).entered();
+            if let Err(err) = system.run(scene, delta) {
+                error!("System {} failed: {err:#}", system.name());
+            }
+        }
+        if let Some(input_state) = input_state.as_ref() {
+            input_state.write_or_recover().end_frame();
+        }
+    }
+}
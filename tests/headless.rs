@@ -0,0 +1,260 @@
+//Copyright 2024 Callum Dowling
+//
+//Licensed under the Apache License, Version 2.0 (the "License");
+//you may not use this file except in compliance with the License.
+//You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+//Unless required by applicable law or agreed to in writing, software
+//distributed under the License is distributed on an "AS IS" BASIS,
+//WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//See the License for the specific language governing permissions and
+//limitations under the License.
+
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use glam::{Mat4, Vec2, Vec3};
+use rand::Rng;
+use winit::keyboard::KeyCode;
+
+use balloon::component::controller::Controller;
+use balloon::component::controller::behaviour_tree_controller::{
+    ActionRegistry, BehaviourTreeController, NodeDefinition, Value,
+};
+use balloon::component::controller::follow_camera_controller::FollowCameraController;
+use balloon::component::controller::orbit_camera_controller::OrbitCameraController;
+use balloon::component::controller::rotator_controller::RotatorController;
+use balloon::component::controller::state_machine_controller::{StateContext, StateMachineController};
+use balloon::component::transform_component::TransformComponent;
+use balloon::headless::HeadlessRunner;
+use balloon::replay::{InputRecorder, InputReplay, ReplayHeader};
+use balloon::resource::input_state::{Binding, InputEvent, InputState};
+use balloon::resource::mailbox::Mailbox;
+use balloon::resource::random::Random;
+use balloon::resource::time::Time;
+use balloon::scene::lock::RecoverLock;
+use balloon::scene::Scene;
+
+const ROTATOR: usize = 0;
+const WANDERER: usize = 1;
+const PATROL: usize = 2;
+const FOLLOW_CAMERA: usize = 3;
+const ORBIT_CAMERA: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Phase {
+    Idle,
+    Moving,
+}
+
+fn add_controller(scene: &mut Scene, entity: usize, controller: impl Controller + 'static) {
+    let controller: Box<dyn Controller> = Box::new(controller);
+    scene.add_component_to_entity(entity, Arc::new(RwLock::new(controller)));
+}
+
+fn with_transform(context: &StateContext, op: impl FnOnce(&mut TransformComponent)) -> Result<()> {
+    let scene = context.scene.read_or_recover();
+    let transforms = scene
+        .get_component_vec::<TransformComponent>()
+        .context("Scene has no transform components")?;
+    let mut transforms = transforms.write_or_recover();
+    let transform = transforms[context.index]
+        .as_mut()
+        .context("Entity has no transform component")?;
+    op(transform);
+    Ok(())
+}
+
+fn jump_pressed(context: &StateContext) -> bool {
+    let scene = context.scene.read_or_recover();
+    scene
+        .get_resource::<InputState>()
+        .is_some_and(|input| input.read_or_recover().pressed("jump"))
+}
+
+//Idles for half a second, or until jump is pressed, then walks at 2 units a second in a
+//direction drawn from Random
+fn wanderer() -> StateMachineController<Phase, Vec3> {
+    StateMachineController::with_data(Phase::Idle, Vec3::ZERO)
+        .transition(Phase::Idle, Phase::Moving, |_, context| {
+            context.time_in_state >= 0.5 || jump_pressed(context)
+        })
+        .on_enter(Phase::Moving, |direction, context| {
+            let scene = context.scene.read_or_recover();
+            let frame = scene
+                .get_resource::<Time>()
+                .context("Scene has no Time")?
+                .read_or_recover()
+                .frame();
+            let mut rng = scene
+                .get_resource::<Random>()
+                .context("Scene has no Random")?
+                .read_or_recover()
+                .entity_rng(context.index, frame);
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            *direction = Vec3::new(angle.cos(), 0.0, angle.sin());
+            Ok(())
+        })
+        .on_update(Phase::Moving, |direction, context| {
+            let step = Mat4::from_translation(*direction * 2.0 * context.delta);
+            with_transform(context, |transform| transform.transform_op(|transform| step * transform))
+        })
+}
+
+fn action(name: &str, params: Vec<(&str, Value)>) -> NodeDefinition {
+    NodeDefinition::Action {
+        name: name.to_string(),
+        params: params
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    }
+}
+
+//Walks 2 units along Z over a second, then turns on the spot forever
+fn patrol() -> BehaviourTreeController {
+    let definition = NodeDefinition::Selector(vec![
+        NodeDefinition::Sequence(vec![
+            action("blackboard_is", vec![("key", Value::Text("arrived".into())), ("value", Value::Bool(true))]),
+            action("rotate", vec![("axis", Value::Vec3([0.0, 1.0, 0.0])), ("speed", Value::Float(1.0)), ("seconds", Value::Float(2.0))]),
+        ]),
+        NodeDefinition::Sequence(vec![
+            action("translate", vec![("velocity", Value::Vec3([0.0, 0.0, 2.0])), ("seconds", Value::Float(1.0))]),
+            action("set_blackboard", vec![("key", Value::Text("arrived".into())), ("value", Value::Bool(true))]),
+        ]),
+    ]);
+    BehaviourTreeController::new(&definition, &ActionRegistry::with_builtins()).unwrap()
+}
+
+fn make_scene(seed: u64) -> Arc<RwLock<Scene>> {
+    let mut scene = Scene {
+        entities_index: 0,
+        component_map: HashMap::new(),
+        resources: HashMap::new(),
+    };
+    scene.insert_resource(Mailbox::new());
+    scene.insert_resource(Time::new());
+    scene.insert_resource(Random::new(seed));
+
+    for _ in 0..5 {
+        let entity = scene.new_entity();
+        scene.add_component_to_entity(entity, TransformComponent::new());
+    }
+    add_controller(&mut scene, ROTATOR, RotatorController::new());
+    add_controller(&mut scene, WANDERER, wanderer());
+    add_controller(&mut scene, PATROL, patrol());
+    add_controller(&mut scene, FOLLOW_CAMERA, FollowCameraController::new(PATROL, Vec3::new(0.0, 2.0, -5.0)));
+    add_controller(&mut scene, ORBIT_CAMERA, OrbitCameraController::new(WANDERER, 10.0));
+    Arc::new(RwLock::new(scene))
+}
+
+fn transform(scene: &Scene, entity: usize) -> Mat4 {
+    let transforms = scene.get_component_vec::<TransformComponent>().unwrap();
+    let transforms = transforms.read_or_recover();
+    transforms[entity].as_ref().unwrap().transform
+}
+
+fn describe(scene: &Scene, entity: usize) -> String {
+    let controllers = scene
+        .get_component_vec::<Arc<RwLock<Box<dyn Controller>>>>()
+        .unwrap();
+    let controllers = controllers.read_or_recover();
+    let description = controllers[entity].as_ref().unwrap().read_or_recover().describe();
+    description
+}
+
+//Everything a replay has to reproduce, compared exactly
+fn snapshot(scene: &Scene) -> Vec<(Mat4, String)> {
+    (0..scene.entities_index)
+        .map(|entity| (transform(scene, entity), describe(scene, entity)))
+        .collect()
+}
+
+#[test]
+fn controllers_drive_the_scene() {
+    let mut runner = HeadlessRunner::new(make_scene(7));
+
+    runner.run_frames(30);
+    {
+        let scene = runner.scene();
+        let scene = scene.read_or_recover();
+        let patrol = transform(&scene, PATROL).w_axis;
+        assert!((patrol.z - 1.0).abs() < 1e-3, "patrol at {patrol:?}");
+        assert!(describe(&scene, PATROL).contains("running: [translate]"));
+        assert!(describe(&scene, WANDERER).contains("state: Idle"));
+        assert_eq!(transform(&scene, WANDERER), Mat4::IDENTITY);
+    }
+
+    let frames = runner
+        .run_until(60, |scene| describe(scene, WANDERER).contains("state: Moving"))
+        .unwrap();
+    assert!(frames <= 2, "wanderer took {frames} extra frames to start moving");
+
+    runner.run_frames(60);
+    let scene = runner.scene();
+    let scene = scene.read_or_recover();
+    let wanderer = transform(&scene, WANDERER).w_axis;
+    let distance = Vec2::new(wanderer.x, wanderer.z).length();
+    assert!((distance - 2.0).abs() < 0.1, "wanderer {distance} from the origin");
+    assert_eq!(wanderer.y, 0.0);
+
+    let patrol = transform(&scene, PATROL).w_axis;
+    assert!((patrol.z - 2.0).abs() < 1e-3, "patrol at {patrol:?}");
+    assert!(describe(&scene, PATROL).contains("running: [rotate]"));
+
+    let expected = Mat4::from_axis_angle(Vec3::X, 0.02 * runner.frame() as f32);
+    assert!(transform(&scene, ROTATOR).abs_diff_eq(expected, 1e-4));
+    assert!(runner.disabled_controllers().is_empty());
+}
+
+#[test]
+fn replay_matches_recorded_run() {
+    const SEED: u64 = 1234;
+    const FRAMES: u64 = 120;
+    let path = std::env::temp_dir().join(format!("balloon_headless_{}.ron", std::process::id()));
+    let mut input: Vec<(u64, InputEvent)> = vec![
+        (2, InputEvent::Pressed(Binding::Key(KeyCode::Tab))),
+        (3, InputEvent::Released(Binding::Key(KeyCode::Tab))),
+        (10, InputEvent::Pressed(Binding::Key(KeyCode::Space))),
+        (10, InputEvent::Scroll(Vec2::new(0.0, 1.0))),
+        (12, InputEvent::Released(Binding::Key(KeyCode::Space))),
+    ];
+    input.extend((5..40).map(|frame| (frame, InputEvent::MouseMotion(Vec2::new(4.0, -1.5)))));
+    input.sort_by_key(|(frame, _)| *frame);
+
+    let mut recorder = InputRecorder::create(&path, &ReplayHeader::new(SEED)).unwrap();
+    let mut live = HeadlessRunner::new(make_scene(SEED));
+    for frame in 0..FRAMES {
+        for (_, event) in input.iter().filter(|(at, _)| *at == frame) {
+            recorder.record(frame, event).unwrap();
+            live.send_input(*event);
+        }
+        recorder.flush().unwrap();
+        live.step();
+    }
+    drop(recorder);
+
+    let replay = InputReplay::from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let mut replayed = HeadlessRunner::new(make_scene(replay.header().seed)).with_replay(replay);
+    replayed.run_replay();
+    replayed.run_frames(FRAMES - replayed.frame());
+
+    let live_scene = live.scene();
+    let live_scene = live_scene.read_or_recover();
+    let replayed_scene = replayed.scene();
+    let replayed_scene = replayed_scene.read_or_recover();
+    //The input has to have reached the controllers for the comparison to mean anything
+    let wanderer = transform(&live_scene, WANDERER).w_axis;
+    assert!(Vec2::new(wanderer.x, wanderer.z).length() > 3.5, "jump didn't start the wanderer early");
+    assert!(describe(&live_scene, ORBIT_CAMERA).contains("distance: 9.00"));
+    assert_ne!(transform(&live_scene, FOLLOW_CAMERA), Mat4::IDENTITY);
+    assert_eq!(snapshot(&live_scene), snapshot(&replayed_scene));
+    assert!(live.disabled_controllers().is_empty());
+    assert!(replayed.disabled_controllers().is_empty());
+}